@@ -1,4 +1,6 @@
-use airap::Airap;
+use airap::feature::{feature_flags, Feature};
+use airap::history::HistoryStore;
+use airap::{Device, Runner};
 use gtk::cairo;
 use gtk::gio;
 
@@ -22,10 +24,9 @@ use std::cell::RefCell;
 use std::error::Error;
 use std::ops::Div;
 use std::rc::Rc;
-use std::sync::mpsc;
-use std::sync::mpsc::channel;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::thread;
 use std::time::Duration;
 
 use super::PlotterInner;
@@ -33,8 +34,12 @@ use super::PlotterInner;
 // https://stackoverflow.com/questions/66510406/gtk-rs-how-to-update-view-from-another-thread
 // Example Graph: https://github.com/GuillaumeGomez/process-viewer/tree/master
 
+/// Longest time interval of the plotter in milliseconds
+const MAX_TIME_INTERVAL: f32 = 5000.0;
+
 thread_local!(
-    static AIRAP: RefCell<Option<mpsc::Receiver<Vec<f32>>>> = RefCell::new(None);
+    /// History of the raw audio and the interleaved samples per second of the device
+    static AIRAP: RefCell<Option<(Arc<HistoryStore>, u32)>> = RefCell::new(None);
 );
 
 #[derive(Debug)]
@@ -86,12 +91,20 @@ impl ObjectSubclass for PlotterInnerImp {
     fn new() -> Self {
         info!("Creating plotter");
 
-        let (tx, rx) = channel::<Vec<f32>>();
-        let mut airap = Airap::new().unwrap();
-        airap.on_raw(move |data| {
-            tx.send(data.to_vec()).unwrap();
-        });
-        AIRAP.with(|r| *r.borrow_mut() = Some(rx));
+        let device = Device::default().unwrap();
+        let rate = device.spec.rate * device.spec.channels as u32;
+        let history = Arc::new(HistoryStore::new(
+            &device,
+            Duration::from_secs_f32(MAX_TIME_INTERVAL / 1000.0),
+        ));
+        let mut runner = Runner::new();
+        runner.set_device(device);
+        runner
+            .subscribe(&[Feature::default(feature_flags::RAW)])
+            .unwrap();
+        let writer = history.clone();
+        thread::spawn(move || runner.listen(move |e| writer.write(&e)).unwrap());
+        AIRAP.with(|r| *r.borrow_mut() = Some((history, rate)));
 
         Self {
             time_interval_ms: Cell::new(500.0),
@@ -141,14 +154,13 @@ impl PlotterInnerImp {
         println!("A");
         let time_interval = self.time_interval_ms.get();
 
-        let sample_rate = 480000;
-        let plotter_data_len = sample_rate / 2;
-        let mut plotter_data = vec![0.0 as f32; plotter_data_len];
-        // How does each position in values relate to x-axis
-        let x_rate = time_interval.div(plotter_data_len as f32);
-
         AIRAP.with(|airap| {
-            if let Some(rx) = &*airap.borrow() {
+            if let Some((history, rate)) = &*airap.borrow() {
+                let plotter_data_len = (*rate as f32 * time_interval / 1000.0) as usize;
+                let mut plotter_data = vec![0.0 as f32; plotter_data_len];
+                // How does each position in values relate to x-axis
+                let x_rate = time_interval.div(plotter_data_len as f32);
+
                 println!("Plotting");
                 let root = backend.into_drawing_area();
 
@@ -167,9 +179,10 @@ impl PlotterInnerImp {
                     .draw()
                     .unwrap();
 
-                let data = rx.recv().unwrap();
-                plotter_data[plotter_data_len - data.len()..plotter_data_len]
-                    .copy_from_slice(&data);
+                // the newest samples of the history, fewer at the start
+                let data = history.raw().values;
+                let data = &data[data.len().saturating_sub(plotter_data_len)..];
+                plotter_data[plotter_data_len - data.len()..plotter_data_len].copy_from_slice(data);
 
                 chart
                     .draw_series(AreaSeries::new(
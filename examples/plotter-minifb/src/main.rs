@@ -1,5 +1,6 @@
 use airap::feature::{feature_flags, Feature, RawFeature};
-use airap::{Device, MovingAverageEvent, RawEvent, Runner};
+use airap::history::HistoryStore;
+use airap::{Device, Event, RawEvent, Runner};
 use minifb::{Key, KeyRepeat, Window, WindowOptions};
 use plotters::prelude::*;
 use plotters_bitmap::bitmap_pixel::BGRXPixel;
@@ -8,8 +9,8 @@ use std::borrow::{Borrow, BorrowMut};
 use std::collections::VecDeque;
 use std::error::Error;
 use std::ops::Div;
-use std::sync::mpsc::{self, Receiver};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread::{self, sleep};
 use std::time::{Duration, SystemTime};
 
@@ -20,6 +21,8 @@ const TIME_INTERVAL: f32 = 200.0;
 const SAMPLE_RATE: usize = 48_000;
 const FRAME_RATE: f64 = 30.0; // TODO maximize to frame rate
 const DOWN_SAMPLE: usize = 5;
+/// Moving averages within `TIME_INTERVAL`, one for every 240 frames
+const AVERAGES: usize = (SAMPLE_RATE as f32 / (1000.0 / TIME_INTERVAL) / 240.0) as usize;
 
 struct BufferWrapper(Vec<u32>);
 impl Borrow<[u8]> for BufferWrapper {
@@ -47,69 +50,77 @@ impl BorrowMut<[u32]> for BufferWrapper {
     }
 }
 
-pub struct Audio<const CHANNELS: usize> {
-    channels: [Receiver<Vec<f32>>; CHANNELS],
+pub struct Audio {
+    history: Arc<HistoryStore>,
 }
 
-impl<const CHANNELS: usize> Audio<CHANNELS> {
-    fn load(features: &[Feature; 2]) -> Audio<2> {
-        let (raw_tx, raw_rx) = mpsc::channel::<Vec<f32>>();
-        let (average_tx, average_rx) = mpsc::channel::<Vec<f32>>();
-
+impl Audio {
+    fn load(features: &[Feature]) -> Audio {
+        let device = Device::default().unwrap();
+        let history = HistoryStore::new(&device, Duration::from_secs_f32(TIME_INTERVAL / 1000.0))
+            .track(feature_flags::MOVING_AVERAGE, "average", 1, AVERAGES);
+        let history = Arc::new(history);
+
+        let mut runner = Runner::new();
+        runner.set_device(device);
+        runner.subscribe(features).unwrap();
+        let writer = history.clone();
+        let count = AtomicUsize::new(0);
         thread::spawn(move || {
-            let i = Arc::new(Mutex::new(0));
-            Runner::new()
-                .subscribe(&[Feature::default(feature_flags::MOVING_AVERAGE)])
-                .unwrap()
-                .listen(move |e| match e {
-                    airap::Event::Raw(RawEvent { data, latency }) => {
-                        // print latency every 100 events
-                        let mut mi = i.lock().unwrap();
-                        *mi = (*mi + 1) % 100;
-                        if *mi == 0 {
+            runner
+                .listen(move |e| {
+                    // print latency every 100 raw events
+                    if let Event::Raw(RawEvent { latency, .. }) = &e {
+                        if count.fetch_add(1, Ordering::Relaxed) % 100 == 99 {
                             println!("{:?}", latency.internal)
                         }
-
-                        let data: Vec<f32> = data.iter().step_by(DOWN_SAMPLE).cloned().collect();
-                        raw_tx.send(data).unwrap();
                     }
-                    airap::Event::MovingAverage(MovingAverageEvent { average, .. }) => {
-                        average_tx.send(average).unwrap();
-                    }
-                    _ => {}
+                    writer.write(&e);
                 })
-                .unwrap();
+                .unwrap()
         });
 
-        Audio {
-            channels: [raw_rx, average_rx],
-        }
+        Audio { history }
     }
 
-    fn try_recv(&self) -> [Vec<f32>; CHANNELS] {
-        let mut data: [Vec<f32>; CHANNELS] = vec![Vec::new(); CHANNELS].try_into().expect("static");
-
-        for (i, c) in self.channels.iter().enumerate() {
-            while let Ok(rd) = c.try_recv() {
-                data[i].extend(rd);
-            }
-        }
-
-        data
+    /// Raw samples and averages of the last `TIME_INTERVAL`, oldest first
+    fn window(&self) -> [Vec<f32>; 2] {
+        let raw = self
+            .history
+            .raw()
+            .values
+            .into_iter()
+            .step_by(DOWN_SAMPLE)
+            .collect();
+        let average = self
+            .history
+            .get(feature_flags::MOVING_AVERAGE)
+            .map(|snapshot| snapshot.values)
+            .unwrap_or_default();
+
+        [raw, average]
     }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     simple_logger::SimpleLogger::new().init().unwrap();
 
-    let audio = Audio::<2>::load(&[Feature::default(feature_flags::RAW), Feature::MovingAverage]);
+    let audio = Audio::load(&[Feature::default(feature_flags::RAW), Feature::MovingAverage]);
 
     show_window(audio)?;
     // loop {}
     Ok(())
 }
 
-pub fn show_window(audio: Audio<2>) -> Result<(), Box<dyn Error>> {
+/// Replace the end of `plotted` with the newest values of `window`, which is shorter until the
+/// first `TIME_INTERVAL` passed
+fn replace_newest(plotted: &mut [f32], window: &[f32]) {
+    let window = &window[window.len().saturating_sub(plotted.len())..];
+    let start = plotted.len() - window.len();
+    plotted[start..].copy_from_slice(window);
+}
+
+pub fn show_window(audio: Audio) -> Result<(), Box<dyn Error>> {
     let mut buf = BufferWrapper(vec![0u32; W * H]);
 
     let mut window = Window::new(
@@ -151,19 +162,16 @@ pub fn show_window(audio: Audio<2>) -> Result<(), Box<dyn Error>> {
     // How does each position in values relate to x-axis
     let plotter_raw_data_x_rate = TIME_INTERVAL.div(plotter_raw_data_len);
 
-    // fragrate / 4 = 240
-    let plotter_average_data_len = SAMPLE_RATE as f32 / (1000.0 / TIME_INTERVAL) / 240 as f32;
+    let plotter_average_data_len = AVERAGES as f32;
     // Adding an extra data point because we also want 0 to contain data
     let mut plotter_average_data = vec![0.0 as f32; plotter_average_data_len as usize + 1];
     let plotter_average_data_x_rate = TIME_INTERVAL.div(plotter_average_data_len as f32);
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
         {
-            let [raw_data, average_data] = audio.try_recv();
-            plotter_raw_data.drain(0..raw_data.len());
-            plotter_raw_data.extend(raw_data);
-            plotter_average_data.drain(0..average_data.len());
-            plotter_average_data.extend(average_data);
+            let [raw_data, average_data] = audio.window();
+            replace_newest(&mut plotter_raw_data, &raw_data);
+            replace_newest(&mut plotter_average_data, &average_data);
 
             let root = BitMapBackend::<BGRXPixel>::with_buffer_and_format(
                 buf.borrow_mut(),
@@ -0,0 +1,393 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use pulse::sample::{Format, Spec};
+
+use crate::error::{AirapError, AirapErrorKind};
+use crate::feature::{feature_flags, processor, Feature, FeatureStore, MULTI_CHANNEL};
+use crate::fields::{field_value, Value};
+use crate::latency::{Instant, Latency};
+use crate::{Event, LoudnessReset, RawEvent};
+
+/// Frames in a raw event, also the resolution of the event times
+const BLOCK: usize = 512;
+
+pub enum Source<'a> {
+    /// Interleaved samples
+    Samples {
+        data: &'a [f32],
+        channels: u8,
+        rate: u32,
+    },
+    /// Wav file with integer or float samples
+    Wav(&'a Path),
+}
+
+/// Events of a single feature
+#[derive(Debug, Clone, Default)]
+pub struct Series {
+    /// Seconds from the start of the audio to the end of the block the event was computed from
+    pub times: Vec<f64>,
+    pub events: Vec<Event<'static>>,
+}
+
+impl Series {
+    /// Values of a numeric or boolean field of the events, e.g. `bpm` of tempo
+    pub fn scalar(&self, field: &str) -> Option<Vec<f32>> {
+        self.events
+            .iter()
+            .map(|e| match field_value(e, field)? {
+                Value::Bool(b) => Some(b as u8 as f32),
+                Value::Int(i) => Some(i as f32),
+                Value::Float(f) => Some(f),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Values of a list field of the events, e.g. `magnitudes` of spectrum
+    pub fn vector(&self, field: &str) -> Option<Vec<Vec<f32>>> {
+        self.events
+            .iter()
+            .map(|e| match field_value(e, field)? {
+                Value::Floats(values) | Value::Samples(values) => Some(values.to_vec()),
+                Value::Ints(values) => Some(values.iter().map(|v| *v as f32).collect()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Output of `analyze` for every requested feature
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    series: HashMap<u32, Series>,
+    /// Length of the audio in seconds
+    pub duration: f64,
+}
+
+impl Analysis {
+    pub fn get(&self, flag: u32) -> Option<&Series> {
+        self.series.get(&flag)
+    }
+}
+
+/// Process all audio of `source` on the calling thread and collect the events of `features`.
+/// The output is the same for the same input, the events of dependencies are only kept when they
+/// are requested as well. Features that only handle mono audio get the mix of all channels.
+pub fn analyze(source: Source, features: &[Feature]) -> Result<Analysis, AirapError> {
+    let wav;
+    let (data, channels, rate) = match source {
+        Source::Samples {
+            data,
+            channels,
+            rate,
+        } => (data, channels, rate),
+        Source::Wav(path) => {
+            wav = read_wav(path)?;
+            (&wav.0[..], wav.1, wav.2)
+        }
+    };
+    if channels == 0 || rate == 0 {
+        return Err(AirapError::unsupported("Audio without channels or rate"));
+    }
+
+    let spec = Spec {
+        format: Format::F32le,
+        rate,
+        channels,
+    };
+    let mono_spec = Spec {
+        channels: 1,
+        ..spec
+    };
+    let mut feature_store = FeatureStore::new();
+    feature_store.set_features(features)?;
+    let requested = features.iter().fold(0, |flags, f| flags | f.to_flag());
+    let reset = LoudnessReset::default();
+    let mut features = feature_store.features();
+    // the processors handle an event in a stable order
+    features.sort_by_key(|f| f.to_flag());
+    let mut processors: Vec<_> = features
+        .iter()
+        .filter_map(|f| {
+            let multi_channel = MULTI_CHANNEL & f.to_flag() > 0;
+            let spec = if multi_channel { &spec } else { &mono_spec };
+            processor(f, &feature_store, spec, &reset).map(|p| (f.dependencies(), multi_channel, p))
+        })
+        .collect();
+
+    let mut analysis = Analysis {
+        duration: data.len() as f64 / channels as f64 / rate as f64,
+        ..Default::default()
+    };
+    let mut queue = VecDeque::new();
+    let mut frames = 0;
+    for block in data.chunks(BLOCK * channels as usize) {
+        frames += block.len() / channels as usize;
+        let time = frames as f64 / rate as f64;
+        // offline audio is stamped with its position in the source
+        let latency = Latency {
+            internal: Instant::None,
+            airap: Instant::None,
+            captured: SystemTime::UNIX_EPOCH + Duration::from_secs_f64(time),
+        };
+        let mono = (channels > 1).then(|| {
+            let data = block
+                .chunks_exact(channels as usize)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+                .collect::<Vec<_>>();
+            Event::Raw(RawEvent {
+                data: Cow::Owned(data),
+                latency: latency.clone(),
+            })
+        });
+        queue.push_back(Event::Raw(RawEvent {
+            data: Cow::Borrowed(block),
+            latency,
+        }));
+        // every event reaches its dependents before the events they emit
+        while let Some(event) = queue.pop_front() {
+            let flag = event.to_flag();
+            for (dependencies, multi_channel, processor) in processors.iter_mut() {
+                if *dependencies & flag == 0 {
+                    continue;
+                }
+                let event = match &mono {
+                    Some(mono) if flag == feature_flags::RAW && !*multi_channel => mono,
+                    _ => &event,
+                };
+                processor.process(event, &mut |e| queue.push_back(e));
+            }
+            if requested & flag > 0 {
+                let series = analysis.series.entry(flag).or_default();
+                series.times.push(time);
+                series.events.push(event.into_owned());
+            }
+        }
+    }
+
+    Ok(analysis)
+}
+
+/// Interleaved samples, channels and rate of a pcm or float wav file
+fn read_wav(path: &Path) -> Result<(Vec<f32>, u8, u32), AirapError> {
+    let invalid =
+        |message: &str| AirapError::new(format!("Invalid wav: {message}"), AirapErrorKind::Io);
+    let bytes = fs::read(path)?;
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("not a riff wave file"));
+    }
+
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+    let mut format = None;
+    let mut position = 12;
+    while position + 8 <= bytes.len() {
+        let id = &bytes[position..position + 4];
+        let size = u32_at(position + 4) as usize;
+        let body = position + 8;
+        let end = (body + size).min(bytes.len());
+        match id {
+            b"fmt " => {
+                // the file can end before the size of the chunk
+                if end - body < 16 {
+                    return Err(invalid("short format chunk"));
+                }
+                let mut tag = u16_at(body);
+                // extensible format, the actual format is the start of the sub format guid
+                if tag == 0xfffe {
+                    if end - body < 26 {
+                        return Err(invalid("short extensible format chunk"));
+                    }
+                    tag = u16_at(body + 24);
+                }
+                format = Some((tag, u16_at(body + 2), u32_at(body + 4), u16_at(body + 14)));
+            }
+            b"data" => {
+                let Some((tag, channels, rate, bits)) = format else {
+                    return Err(invalid("data before format"));
+                };
+                let data = &bytes[body..end];
+                let samples: Vec<f32> = match (tag, bits) {
+                    (1, 8) => data.iter().map(|b| (*b as f32 - 128.0) / 128.0).collect(),
+                    (1, 16) => data
+                        .chunks_exact(2)
+                        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+                        .collect(),
+                    (1, 24) => data
+                        .chunks_exact(3)
+                        .map(|b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2147483648.0)
+                        .collect(),
+                    (1, 32) => data
+                        .chunks_exact(4)
+                        .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2147483648.0)
+                        .collect(),
+                    (3, 32) => data
+                        .chunks_exact(4)
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect(),
+                    _ => {
+                        return Err(invalid(&format!(
+                            "unsupported format {tag} with {bits} bits"
+                        )))
+                    }
+                };
+                return Ok((samples, channels as u8, rate));
+            }
+            _ => {}
+        }
+        // chunks are padded to an even size
+        position = body + size + size % 2;
+    }
+    Err(invalid("no data"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const RATE: u32 = 48000;
+
+    /// Wav file with a format chunk of `format` and a data chunk of `data`
+    fn wav(name: &str, format: &[u8], data: &[u8]) -> PathBuf {
+        let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+        bytes.extend(b"fmt ");
+        bytes.extend((format.len() as u32).to_le_bytes());
+        bytes.extend(format);
+        bytes.extend(b"data");
+        bytes.extend((data.len() as u32).to_le_bytes());
+        bytes.extend(data);
+        let size = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&size.to_le_bytes());
+        let path = std::env::temp_dir().join(format!("airap-{}-{name}.wav", std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn format(tag: u16, channels: u16, bits: u16) -> Vec<u8> {
+        let block = channels * bits / 8;
+        let mut format = tag.to_le_bytes().to_vec();
+        format.extend(channels.to_le_bytes());
+        format.extend(RATE.to_le_bytes());
+        format.extend((RATE * block as u32).to_le_bytes());
+        format.extend(block.to_le_bytes());
+        format.extend(bits.to_le_bytes());
+        format
+    }
+
+    fn read(path: PathBuf) -> Result<(Vec<f32>, u8, u32), AirapError> {
+        let result = read_wav(&path);
+        fs::remove_file(path).unwrap();
+        result
+    }
+
+    #[test]
+    fn reads_pcm_and_float() {
+        let data: Vec<u8> = [0i16, 16384, -32768, 32767]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let (samples, channels, rate) = read(wav("pcm16", &format(1, 2, 16), &data)).unwrap();
+        assert_eq!((channels, rate), (2, RATE));
+        assert_eq!(samples, [0.0, 0.5, -1.0, 32767.0 / 32768.0]);
+
+        let data = [0x00, 0x00, 0x40, 0x00, 0x00, 0x80];
+        let (samples, _, _) = read(wav("pcm24", &format(1, 1, 24), &data)).unwrap();
+        assert_eq!(samples, [0.5, -1.0]);
+
+        let data: Vec<u8> = [0.25f32, -0.75]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let (samples, _, _) = read(wav("float", &format(3, 1, 32), &data)).unwrap();
+        assert_eq!(samples, [0.25, -0.75]);
+    }
+
+    #[test]
+    fn reads_extensible_format() {
+        let mut extensible = format(0xfffe, 1, 32);
+        extensible.extend(22u16.to_le_bytes());
+        extensible.extend(32u16.to_le_bytes());
+        extensible.extend(4u32.to_le_bytes());
+        // sub format guid of float samples
+        extensible.extend(3u16.to_le_bytes());
+        extensible.extend([0; 14]);
+        let data = 0.5f32.to_le_bytes();
+        let (samples, _, _) = read(wav("extensible", &extensible, &data)).unwrap();
+        assert_eq!(samples, [0.5]);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let path = wav("truncated", &format(1, 1, 16), &[]);
+        let mut bytes = fs::read(&path).unwrap();
+        // the format chunk claims 16 bytes but the file ends after 6
+        bytes.truncate(12 + 8 + 6);
+        fs::write(&path, bytes).unwrap();
+        assert!(read(path).is_err());
+
+        let path = wav("riff", &format(1, 1, 16), &[]);
+        fs::write(&path, b"RIFX").unwrap();
+        assert!(read(path).is_err());
+    }
+
+    fn analyze_samples(data: &[f32], channels: u8, flag: u32, field: &str) -> Vec<f32> {
+        let source = Source::Samples {
+            data,
+            channels,
+            rate: RATE,
+        };
+        let analysis = analyze(source, &[Feature::default(flag)]).unwrap();
+        analysis.get(flag).unwrap().scalar(field).unwrap()
+    }
+
+    #[test]
+    fn pitch_of_sine() {
+        let sine: Vec<f32> = (0..RATE as usize)
+            .map(|i| 0.5 * (i as f32 * 440.0 * TAU / RATE as f32).sin())
+            .collect();
+        let frequencies = analyze_samples(&sine, 1, feature_flags::PITCH, "frequency");
+        let last = frequencies.last().unwrap();
+        assert!((last - 440.0).abs() < 2.0, "{last}");
+    }
+
+    #[test]
+    fn tempo_of_clicks_for_any_channels() {
+        // 120 bpm for 10 seconds
+        let clicks: Vec<f32> = (0..RATE as usize * 10)
+            .map(|i| {
+                if i % (RATE as usize / 2) < 32 {
+                    0.9
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let stereo: Vec<f32> = clicks.iter().flat_map(|s| [*s, *s]).collect();
+        for (data, channels) in [(&clicks, 1), (&stereo, 2)] {
+            let bpm = analyze_samples(data, channels, feature_flags::TEMPO, "bpm");
+            let last = bpm.last().unwrap();
+            assert!((last - 120.0).abs() < 2.0, "{channels} channels: {last}");
+        }
+    }
+
+    #[test]
+    fn loudness_of_stereo_sine() {
+        // a 997 Hz sine 20 dB below full scale on both channels is -20 LUFS
+        let stereo: Vec<f32> = (0..RATE as usize * 5)
+            .map(|i| (i as f64 * 997.0 / RATE as f64).fract() as f32)
+            .map(|phase| 0.1 * (phase * TAU).sin())
+            .flat_map(|s| [s, s])
+            .collect();
+        let integrated = analyze_samples(&stereo, 2, feature_flags::LOUDNESS, "integrated");
+        let last = integrated.last().unwrap();
+        assert!((last + 20.0).abs() < 0.2, "{last}");
+    }
+}
@@ -9,22 +9,59 @@ use pulse::operation::{self, Operation, State};
 use pulse::proplist::Proplist;
 use pulse::sample::{Format, Spec};
 use pulse::stream::{self, FlagSet as StreamFlagSet, PeekResult, Stream};
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::ops::Deref;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 use std::thread::{self, JoinHandle};
+use std::time::SystemTime;
 
 use crate::error::AirapError;
 use crate::{Instant, Latency, RawEvent};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Device {
     pub name: String,
+    #[cfg_attr(feature = "serde", serde(with = "spec"))]
     pub spec: Spec,
     pub monitor_of_sink_name: Option<String>,
 }
 
+/// Spec with the format as its pulseaudio name, e.g. `float32le`
+#[cfg(feature = "serde")]
+mod spec {
+    use pulse::sample::{Format, Spec};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct SpecDef {
+        format: String,
+        rate: u32,
+        channels: u8,
+    }
+
+    pub fn serialize<S: Serializer>(spec: &Spec, serializer: S) -> Result<S::Ok, S::Error> {
+        SpecDef {
+            format: spec.format.to_string().unwrap_or_default().into_owned(),
+            rate: spec.rate,
+            channels: spec.channels,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Spec, D::Error> {
+        let spec = SpecDef::deserialize(deserializer)?;
+        Ok(Spec {
+            format: Format::parse(&spec.format),
+            rate: spec.rate,
+            channels: spec.channels,
+        })
+    }
+}
+
 impl<'a> From<&pulse::context::introspect::SourceInfo<'a>> for Device {
     fn from(v: &pulse::context::introspect::SourceInfo) -> Self {
         let mut spec = v.sample_spec;
@@ -152,9 +189,11 @@ fn get_context(mainloop: &Rc<RefCell<Mainloop>>) -> Result<Rc<RefCell<Context>>,
     return Ok(context);
 }
 
-pub fn raw<'a, F>(device: &Device, cb: F) -> Result<(), AirapError>
+/// Record from `device` until `stop` is set. The data of the events borrows the buffer of the
+/// stream, which is reused after `cb` returns, so `cb` has to copy it to keep it.
+pub fn raw<F>(device: &Device, stop: &AtomicBool, cb: F) -> Result<(), AirapError>
 where
-    F: Fn(RawEvent<'a>),
+    F: for<'b> Fn(RawEvent<'b>),
 {
     let mainloop = Rc::new(RefCell::new(
         Mainloop::new().expect("Failed to create mainloop"),
@@ -214,7 +253,7 @@ where
     debug!("Buffer size: '{:?}'", stream.get_buffer_attr());
 
     stream.update_timing_info(None);
-    loop {
+    while !stop.load(Ordering::Acquire) {
         iterate_mainloop(&mainloop)?;
         if let Some(size) = stream.readable_size() {
             if size > 0 {
@@ -229,10 +268,12 @@ where
                     assert!(prefix.len() == 0);
                     assert!(suffix.len() == 0);
 
+                    let internal: Instant = internal_latency.into();
                     cb(RawEvent {
-                        data,
+                        data: Cow::Borrowed(data),
                         latency: Latency {
-                            internal: internal_latency.into(),
+                            captured: internal.before(SystemTime::now()),
+                            internal,
                             airap: Instant::None,
                         },
                     });
@@ -247,4 +288,5 @@ where
         //     stream.uncork(None);
         // }
     }
+    Ok(())
 }
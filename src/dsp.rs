@@ -0,0 +1,172 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+/// Splits a continuous stream of samples into overlapping frames
+#[derive(Debug, Clone)]
+pub struct Framer {
+    buffer: Vec<f32>,
+    size: usize,
+    hop: usize,
+}
+
+impl Framer {
+    /// `size` is the amount of samples in a frame and `hop` the amount of samples between the start of two frames
+    pub fn new(size: usize, hop: usize) -> Self {
+        assert!(
+            hop > 0 && hop <= size,
+            "hop must be between 1 and the frame size"
+        );
+        Self {
+            buffer: Vec::with_capacity(size * 2),
+            size,
+            hop,
+        }
+    }
+
+    /// Add samples, calling `f` for every frame that got completed
+    pub fn push<F>(&mut self, data: &[f32], mut f: F)
+    where
+        F: FnMut(&[f32]),
+    {
+        self.buffer.extend_from_slice(data);
+        let mut start = 0;
+        while self.buffer.len() - start >= self.size {
+            f(&self.buffer[start..start + self.size]);
+            start += self.hop;
+        }
+        self.buffer.drain(..start);
+    }
+}
+
+/// Mean of the squared samples
+pub fn mean_square(data: &[f32]) -> f32 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    data.iter().map(|s| s * s).sum::<f32>() / data.len() as f32
+}
+
+/// Refine the position of a peak at `i` using the neighbouring values, returns the fractional offset to `i`
+pub fn parabolic_offset(left: f32, center: f32, right: f32) -> f32 {
+    let denominator = left - 2.0 * center + right;
+    if denominator.abs() < f32::EPSILON {
+        return 0.0;
+    }
+    (0.5 * (left - right) / denominator).clamp(-0.5, 0.5)
+}
+
+/// Fractional midi note number of a frequency in Hz where 69 is A4 at 440Hz
+pub fn frequency_to_midi(frequency: f32) -> f32 {
+    69.0 + 12.0 * (frequency / 440.0).log2()
+}
+
+/// Hann window of `size` samples
+pub fn hann(size: usize) -> Vec<f32> {
+    (0..size)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / size as f32).cos())
+        .collect()
+}
+
+/// Windowed fft returning the magnitude of every bin up to the nyquist frequency
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    /// Scales magnitudes so a full scale sine has a peak of 1
+    scale: f32,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(size: usize) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(size);
+        let window = hann(size);
+        let scale = 2.0 / window.iter().sum::<f32>();
+        Self {
+            scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+            buffer: vec![Complex::default(); size],
+            fft,
+            window,
+            scale,
+        }
+    }
+
+    /// Amount of bins returned by `magnitudes`
+    pub fn bins(&self) -> usize {
+        self.window.len() / 2 + 1
+    }
+
+    pub fn magnitudes(&mut self, frame: &[f32]) -> Vec<f32> {
+        for ((b, s), w) in self.buffer.iter_mut().zip(frame).zip(&self.window) {
+            *b = Complex::new(s * w, 0.0);
+        }
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+        self.buffer[..self.bins()]
+            .iter()
+            .map(|c| c.norm() * self.scale)
+            .collect()
+    }
+}
+
+/// Second order iir filter in transposed direct form II
+#[derive(Debug, Clone)]
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    /// `a` are the feedback coefficients without the leading 1
+    pub fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, z: [0.0; 2] }
+    }
+
+    /// Butterworth low pass from the audio eq cookbook
+    pub fn low_pass(frequency: f32, rate: f32) -> Self {
+        let (cos, alpha) = Self::angle(frequency, rate);
+        let a0 = 1.0 + alpha;
+        let b = (1.0 - cos) / a0;
+        Self::new([b / 2.0, b, b / 2.0], [-2.0 * cos / a0, (1.0 - alpha) / a0])
+    }
+
+    /// Butterworth high pass from the audio eq cookbook
+    pub fn high_pass(frequency: f32, rate: f32) -> Self {
+        let (cos, alpha) = Self::angle(frequency, rate);
+        let a0 = 1.0 + alpha;
+        let b = (1.0 + cos) / a0;
+        Self::new(
+            [b / 2.0, -b, b / 2.0],
+            [-2.0 * cos / a0, (1.0 - alpha) / a0],
+        )
+    }
+
+    fn angle(frequency: f32, rate: f32) -> (f64, f64) {
+        let w0 = 2.0 * std::f64::consts::PI * frequency as f64 / rate as f64;
+        (w0.cos(), w0.sin() / (2.0 * std::f64::consts::FRAC_1_SQRT_2))
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        let x = x as f64;
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y as f32
+    }
+}
+
+/// Decibels relative to full scale of an amplitude
+pub fn amplitude_to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.log10()
+}
+
+/// Coefficient for a one pole smoother that reaches ~63% of a step after `ms` milliseconds
+pub fn smoothing_coefficient(ms: f32, rate: f32) -> f32 {
+    if ms <= 0.0 {
+        return 1.0;
+    }
+    1.0 - (-1000.0 / (ms * rate)).exp()
+}
@@ -57,3 +57,10 @@ impl From<pulse::error::PAErr> for AirapError {
         )
     }
 }
+
+#[cfg(feature = "alsa")]
+impl From<alsa::Error> for AirapError {
+    fn from(value: alsa::Error) -> Self {
+        Self::new(format!("Alsa Error: {value}"), AirapErrorKind::Io)
+    }
+}
@@ -1,11 +1,166 @@
 use std::{
     collections::{HashMap, HashSet},
     slice::{Iter, IterMut},
+    str::FromStr,
     sync::mpsc::{Receiver, Sender},
 };
 
 use log::info;
 
+use pulse::sample::Spec;
+
+use crate::latency::{Instant, Latency};
+use crate::{error::AirapError, Event, MovingAverageEvent};
+use band::{Band, BandEnergy, BandFilter};
+use beat::Beat;
+use chroma::{Chroma, Key};
+use constant_q::ConstantQ;
+use descriptors::SpectralDescriptors;
+use level::Level;
+use loudness::{Loudness, LoudnessReset};
+use notes::Notes;
+use onset::Onset;
+use pitch::Pitch;
+use silence::Silence;
+use spectrum::Spectrum;
+use tempo::Tempo;
+use voice::VoiceActivity;
+
+pub mod band;
+pub mod beat;
+pub mod chroma;
+pub mod constant_q;
+pub mod descriptors;
+pub mod level;
+pub mod loudness;
+pub mod notes;
+pub mod onset;
+pub mod pitch;
+pub mod silence;
+pub mod spectrum;
+pub mod tempo;
+pub mod voice;
+
+/// Turns events of the dependencies of a feature into events of that feature
+pub trait Processor: Send {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>));
+}
+
+impl<P: Processor + ?Sized> Processor for Box<P> {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        (**self).process(event, emit)
+    }
+}
+
+/// Average of every raw block
+struct MovingAverage;
+
+impl Processor for MovingAverage {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::Raw(r) = event else {
+            return;
+        };
+        let sum: f32 = r.data.iter().sum();
+        emit(Event::MovingAverage(MovingAverageEvent {
+            average: vec![sum / r.data.len() as f32],
+            latency: Latency {
+                internal: Instant::None,
+                airap: Instant::None,
+                captured: r.latency.captured,
+            },
+        }));
+    }
+}
+
+/// Features that process interleaved audio of any number of channels, the others expect mono
+pub(crate) const MULTI_CHANNEL: u32 = feature_flags::MOVING_AVERAGE
+    | feature_flags::LOUDNESS
+    | feature_flags::LEVEL
+    | feature_flags::SILENCE
+    | feature_flags::BAND_ENERGY;
+
+/// Processor of `feature` for audio of `spec`, `None` for features that are sources of events.
+/// Settings of dependencies are taken from `feature_store`.
+pub(crate) fn processor(
+    feature: &Feature,
+    feature_store: &FeatureStore,
+    spec: &Spec,
+    loudness_reset: &LoudnessReset,
+) -> Option<Box<dyn Processor>> {
+    // time between two spectra in seconds
+    let hop = || match feature_store.get(&feature_flags::SPECTRUM) {
+        Some(Feature::Spectrum { hop, .. }) => *hop as f32 / spec.rate as f32,
+        _ => unreachable!("{} depends on spectrum", feature.to_string()),
+    };
+
+    Some(match feature {
+        Feature::Raw { .. } | Feature::DefaultDeviceChange => return None,
+        Feature::MovingAverage => Box::new(MovingAverage),
+        Feature::Onset => Box::new(Onset::default()),
+        Feature::Tempo { min_bpm, max_bpm } => Box::new(Tempo::new(*min_bpm, *max_bpm, spec)),
+        Feature::Beat { beats_per_bar } => {
+            let Some(Feature::Tempo { min_bpm, .. }) = feature_store.get(&feature_flags::TEMPO)
+            else {
+                unreachable!("beat depends on tempo")
+            };
+            Box::new(Beat::new(*beats_per_bar, *min_bpm, spec))
+        }
+        Feature::Pitch {
+            min_frequency,
+            max_frequency,
+            threshold,
+        } => Box::new(Pitch::new(*min_frequency, *max_frequency, *threshold, spec)),
+        Feature::Spectrum { size, hop } => Box::new(Spectrum::new(*size, *hop, spec)),
+        Feature::ConstantQ {
+            bins_per_octave,
+            min_frequency,
+            octaves,
+        } => {
+            let Some(Feature::Spectrum { size, .. }) = feature_store.get(&feature_flags::SPECTRUM)
+            else {
+                unreachable!("constant q depends on spectrum")
+            };
+            Box::new(ConstantQ::new(
+                *bins_per_octave,
+                *min_frequency,
+                *octaves,
+                spec.rate as f32 / *size as f32,
+                size / 2 + 1,
+            ))
+        }
+        Feature::Chroma => Box::new(Chroma::new()),
+        Feature::Key { window, stability } => Box::new(Key::new(*window, *stability, hop())),
+        Feature::Loudness { rate } => Box::new(Loudness::new(*rate, spec, loudness_reset.clone())),
+        Feature::Level {
+            attack,
+            release,
+            hold,
+            rate,
+        } => Box::new(Level::new(*attack, *release, *hold, *rate, spec)),
+        Feature::Silence {
+            threshold,
+            hysteresis,
+            hold,
+        } => Box::new(Silence::new(*threshold, *hysteresis, *hold, spec)),
+        Feature::SpectralDescriptors { rolloff } => Box::new(SpectralDescriptors::new(*rolloff)),
+        Feature::VoiceActivity {
+            threshold,
+            hangover,
+        } => Box::new(VoiceActivity::new(*threshold, *hangover, hop())),
+        Feature::BandEnergy { bands, filter } => {
+            let hop = match filter {
+                BandFilter::Fft => hop(),
+                BandFilter::Iir { .. } => 0.0,
+            };
+            Box::new(BandEnergy::new(bands, *filter, hop, spec))
+        }
+        Feature::Notes {
+            threshold,
+            max_polyphony,
+        } => Box::new(Notes::new(*threshold, *max_polyphony)),
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct RawFeature {
     /// For what latency should we aim in micro seconds (eg 5000 = 5ms)
@@ -20,6 +175,7 @@ impl Default for RawFeature {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Feature {
     Raw {
         buffer_latency: u32,
@@ -27,6 +183,88 @@ pub enum Feature {
     },
     DefaultDeviceChange,
     MovingAverage,
+    Tempo {
+        /// Slowest tempo that can be detected
+        min_bpm: f32,
+        /// Fastest tempo that can be detected
+        max_bpm: f32,
+    },
+    /// Onset envelope of the raw audio that tempo and beat follow
+    Onset,
+    Beat {
+        /// Amount of beats in a bar used for finding the downbeat
+        beats_per_bar: u32,
+    },
+    Pitch {
+        /// Lowest detectable fundamental frequency in Hz
+        min_frequency: f32,
+        /// Highest detectable fundamental frequency in Hz
+        max_frequency: f32,
+        /// Yin threshold between 0 and 1, lower values give less but more certain voiced frames
+        threshold: f32,
+    },
+    Spectrum {
+        /// Amount of samples in a single fft
+        size: usize,
+        /// Amount of samples between two ffts
+        hop: usize,
+    },
+    Chroma,
+    Key {
+        /// Seconds of chroma the key is estimated from
+        window: f32,
+        /// Seconds a different key has to be estimated before it is reported
+        stability: f32,
+    },
+    Loudness {
+        /// Amount of events per second
+        rate: f32,
+    },
+    Level {
+        /// Rise time of the rms in milliseconds
+        attack: f32,
+        /// Fall time of the rms and held peak in milliseconds
+        release: f32,
+        /// Time a peak is held before it decays in milliseconds
+        hold: f32,
+        /// Amount of events per second
+        rate: f32,
+    },
+    Silence {
+        /// Level in dBFS below which the signal counts as silent
+        threshold: f32,
+        /// Amount of dB above `threshold` the signal has to rise to end the silence
+        hysteresis: f32,
+        /// Time in milliseconds a change has to last before it is reported
+        hold: f32,
+    },
+    SpectralDescriptors {
+        /// Part of the energy between 0 and 1 that lies below the rolloff frequency
+        rolloff: f32,
+    },
+    VoiceActivity {
+        /// Amount of dB the speech band has to be above the noise floor
+        threshold: f32,
+        /// Time in milliseconds speech continues after the last speech frame
+        hangover: f32,
+    },
+    BandEnergy {
+        bands: Vec<Band>,
+        filter: BandFilter,
+    },
+    ConstantQ {
+        bins_per_octave: u32,
+        /// Center frequency of the lowest bin in Hz
+        min_frequency: f32,
+        /// Bins above the nyquist frequency of the device are left out
+        octaves: u32,
+    },
+    Notes {
+        /// Level in dBFS a note needs to be detected
+        threshold: f32,
+        /// Maximum amount of notes found in a single frame
+        max_polyphony: usize,
+    },
 }
 impl Feature {
     pub fn default(flag: u32) -> Self {
@@ -37,6 +275,56 @@ impl Feature {
             },
             feature_flags::DEFAULT_DEVICE_CHANGE => Feature::DefaultDeviceChange,
             feature_flags::MOVING_AVERAGE => Feature::MovingAverage,
+            feature_flags::TEMPO => Feature::Tempo {
+                min_bpm: 60.0,
+                max_bpm: 180.0,
+            },
+            feature_flags::ONSET => Feature::Onset,
+            feature_flags::BEAT => Feature::Beat { beats_per_bar: 4 },
+            feature_flags::PITCH => Feature::Pitch {
+                min_frequency: 60.0,
+                max_frequency: 1000.0,
+                threshold: 0.15,
+            },
+            feature_flags::SPECTRUM => Feature::Spectrum {
+                size: 4096,
+                hop: 1024,
+            },
+            feature_flags::CHROMA => Feature::Chroma,
+            feature_flags::KEY => Feature::Key {
+                window: 10.0,
+                stability: 2.0,
+            },
+            feature_flags::LOUDNESS => Feature::Loudness { rate: 10.0 },
+            feature_flags::LEVEL => Feature::Level {
+                attack: 10.0,
+                release: 300.0,
+                hold: 1500.0,
+                rate: 30.0,
+            },
+            feature_flags::SILENCE => Feature::Silence {
+                threshold: -60.0,
+                hysteresis: 6.0,
+                hold: 2000.0,
+            },
+            feature_flags::SPECTRAL_DESCRIPTORS => Feature::SpectralDescriptors { rolloff: 0.85 },
+            feature_flags::VOICE_ACTIVITY => Feature::VoiceActivity {
+                threshold: 9.0,
+                hangover: 300.0,
+            },
+            feature_flags::BAND_ENERGY => Feature::BandEnergy {
+                bands: Band::bass_mid_treble(),
+                filter: BandFilter::Fft,
+            },
+            feature_flags::CONSTANT_Q => Feature::ConstantQ {
+                bins_per_octave: 36,
+                min_frequency: 55.0,
+                octaves: 7,
+            },
+            feature_flags::NOTES => Feature::Notes {
+                threshold: -50.0,
+                max_polyphony: 6,
+            },
             _ => panic!("feature flag '{flag}' does not have a default implementation"),
         }
     }
@@ -45,6 +333,24 @@ impl Feature {
             Feature::Raw { .. } => feature_flags::NONE,
             Feature::DefaultDeviceChange => feature_flags::NONE,
             Feature::MovingAverage => feature_flags::RAW,
+            Feature::Tempo { .. } => feature_flags::ONSET,
+            Feature::Onset => feature_flags::RAW,
+            Feature::Beat { .. } => feature_flags::ONSET | feature_flags::TEMPO,
+            Feature::Pitch { .. } => feature_flags::RAW,
+            Feature::Spectrum { .. } => feature_flags::RAW,
+            Feature::Chroma => feature_flags::CONSTANT_Q,
+            Feature::Key { .. } => feature_flags::CHROMA,
+            Feature::Loudness { .. } => feature_flags::RAW,
+            Feature::Level { .. } => feature_flags::RAW,
+            Feature::Silence { .. } => feature_flags::RAW,
+            Feature::SpectralDescriptors { .. } => feature_flags::RAW | feature_flags::SPECTRUM,
+            Feature::VoiceActivity { .. } => feature_flags::SPECTRUM,
+            Feature::BandEnergy { filter, .. } => match filter {
+                BandFilter::Iir { .. } => feature_flags::RAW,
+                BandFilter::Fft => feature_flags::SPECTRUM,
+            },
+            Feature::ConstantQ { .. } => feature_flags::SPECTRUM,
+            Feature::Notes { .. } => feature_flags::CONSTANT_Q,
         }
     }
     pub fn to_flag(&self) -> u32 {
@@ -52,6 +358,120 @@ impl Feature {
             Feature::Raw { .. } => feature_flags::RAW,
             Feature::DefaultDeviceChange => feature_flags::DEFAULT_DEVICE_CHANGE,
             Feature::MovingAverage => feature_flags::MOVING_AVERAGE,
+            Feature::Tempo { .. } => feature_flags::TEMPO,
+            Feature::Onset => feature_flags::ONSET,
+            Feature::Beat { .. } => feature_flags::BEAT,
+            Feature::Pitch { .. } => feature_flags::PITCH,
+            Feature::Spectrum { .. } => feature_flags::SPECTRUM,
+            Feature::Chroma => feature_flags::CHROMA,
+            Feature::Key { .. } => feature_flags::KEY,
+            Feature::Loudness { .. } => feature_flags::LOUDNESS,
+            Feature::Level { .. } => feature_flags::LEVEL,
+            Feature::Silence { .. } => feature_flags::SILENCE,
+            Feature::SpectralDescriptors { .. } => feature_flags::SPECTRAL_DESCRIPTORS,
+            Feature::VoiceActivity { .. } => feature_flags::VOICE_ACTIVITY,
+            Feature::BandEnergy { .. } => feature_flags::BAND_ENERGY,
+            Feature::ConstantQ { .. } => feature_flags::CONSTANT_Q,
+            Feature::Notes { .. } => feature_flags::NOTES,
+        }
+    }
+    pub fn validate(&self) -> Result<(), AirapError> {
+        match self {
+            Feature::Tempo { min_bpm, max_bpm } if *min_bpm <= 0.0 || min_bpm >= max_bpm => Err(
+                AirapError::feature(format!("invalid bpm range {min_bpm}..{max_bpm}")),
+            ),
+            Feature::Beat { beats_per_bar: 0 } => {
+                Err(AirapError::feature("a bar needs at least one beat"))
+            }
+            Feature::Pitch {
+                min_frequency,
+                max_frequency,
+                ..
+            } if *min_frequency <= 0.0 || min_frequency >= max_frequency => {
+                Err(AirapError::feature(format!(
+                    "invalid frequency range {min_frequency}..{max_frequency}"
+                )))
+            }
+            Feature::Pitch { threshold, .. } if !(0.0..=1.0).contains(threshold) => Err(
+                AirapError::feature(format!("pitch threshold {threshold} not between 0 and 1")),
+            ),
+            Feature::Spectrum { size, hop } if *hop == 0 || hop > size => Err(AirapError::feature(
+                format!("spectrum hop {hop} not between 1 and {size}"),
+            )),
+            Feature::Key { window, stability } if *window <= 0.0 || *stability < 0.0 => {
+                Err(AirapError::feature(format!(
+                    "invalid key window {window} or stability {stability}"
+                )))
+            }
+            Feature::Loudness { rate } if *rate <= 0.0 => Err(AirapError::feature(format!(
+                "loudness rate {rate} must be positive"
+            ))),
+            Feature::Level {
+                attack,
+                release,
+                hold,
+                ..
+            } if *attack < 0.0 || *release < 0.0 || *hold < 0.0 => Err(AirapError::feature(
+                "level attack, release and hold can't be negative",
+            )),
+            Feature::Level { rate, .. } if *rate <= 0.0 => Err(AirapError::feature(format!(
+                "level rate {rate} must be positive"
+            ))),
+            Feature::Silence {
+                hysteresis, hold, ..
+            } if *hysteresis < 0.0 || *hold < 0.0 => Err(AirapError::feature(
+                "silence hysteresis and hold can't be negative",
+            )),
+            Feature::SpectralDescriptors { rolloff } if !(0.0..=1.0).contains(rolloff) => Err(
+                AirapError::feature(format!("rolloff {rolloff} not between 0 and 1")),
+            ),
+            Feature::VoiceActivity { hangover, .. } if *hangover < 0.0 => Err(AirapError::feature(
+                "voice activity hangover can't be negative",
+            )),
+            Feature::BandEnergy { bands, .. } if bands.is_empty() => {
+                Err(AirapError::feature("band energy needs at least one band"))
+            }
+            Feature::BandEnergy { bands, filter } => {
+                for b in bands {
+                    if b.low < 0.0 || b.low >= b.high {
+                        return Err(AirapError::feature(format!(
+                            "invalid band {}..{}",
+                            b.low, b.high
+                        )));
+                    }
+                    if b.attack < 0.0 || b.release < 0.0 {
+                        return Err(AirapError::feature(
+                            "band attack and release can't be negative",
+                        ));
+                    }
+                }
+                match filter {
+                    BandFilter::Iir { rate } if *rate <= 0.0 => Err(AirapError::feature(format!(
+                        "band energy rate {rate} must be positive"
+                    ))),
+                    _ => Ok(()),
+                }
+            }
+            Feature::ConstantQ {
+                bins_per_octave,
+                min_frequency,
+                octaves,
+            } if *bins_per_octave == 0 || *octaves == 0 || *min_frequency <= 0.0 => Err(
+                AirapError::feature("constant q needs bins, octaves and a positive frequency"),
+            ),
+            Feature::ConstantQ {
+                bins_per_octave,
+                octaves,
+                ..
+            } if bins_per_octave.checked_mul(*octaves).is_none() => Err(AirapError::feature(
+                format!("constant q with {octaves} octaves of {bins_per_octave} bins is too large"),
+            )),
+            Feature::Notes {
+                max_polyphony: 0, ..
+            } => Err(AirapError::feature(
+                "notes needs a polyphony of at least one",
+            )),
+            _ => Ok(()),
         }
     }
 }
@@ -61,16 +481,76 @@ impl ToString for Feature {
             Feature::Raw { .. } => "raw",
             Feature::DefaultDeviceChange => "default_device_change",
             Feature::MovingAverage => "moving_average",
+            Feature::Tempo { .. } => "tempo",
+            Feature::Onset => "onset",
+            Feature::Beat { .. } => "beat",
+            Feature::Pitch { .. } => "pitch",
+            Feature::Spectrum { .. } => "spectrum",
+            Feature::Chroma => "chroma",
+            Feature::Key { .. } => "key",
+            Feature::Loudness { .. } => "loudness",
+            Feature::Level { .. } => "level",
+            Feature::Silence { .. } => "silence",
+            Feature::SpectralDescriptors { .. } => "spectral_descriptors",
+            Feature::VoiceActivity { .. } => "voice_activity",
+            Feature::BandEnergy { .. } => "band_energy",
+            Feature::ConstantQ { .. } => "constant_q",
+            Feature::Notes { .. } => "notes",
         }
         .into()
     }
 }
 
+impl FromStr for Feature {
+    type Err = AirapError;
+
+    /// Default configuration of the feature with this name
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let flag = match name {
+            "raw" => feature_flags::RAW,
+            "default_device_change" => feature_flags::DEFAULT_DEVICE_CHANGE,
+            "moving_average" => feature_flags::MOVING_AVERAGE,
+            "tempo" => feature_flags::TEMPO,
+            "onset" => feature_flags::ONSET,
+            "beat" => feature_flags::BEAT,
+            "pitch" => feature_flags::PITCH,
+            "spectrum" => feature_flags::SPECTRUM,
+            "chroma" => feature_flags::CHROMA,
+            "key" => feature_flags::KEY,
+            "loudness" => feature_flags::LOUDNESS,
+            "level" => feature_flags::LEVEL,
+            "silence" => feature_flags::SILENCE,
+            "spectral_descriptors" => feature_flags::SPECTRAL_DESCRIPTORS,
+            "voice_activity" => feature_flags::VOICE_ACTIVITY,
+            "band_energy" => feature_flags::BAND_ENERGY,
+            "constant_q" => feature_flags::CONSTANT_Q,
+            "notes" => feature_flags::NOTES,
+            _ => return Err(AirapError::feature(format!("Unknown feature '{name}'"))),
+        };
+        Ok(Feature::default(flag))
+    }
+}
+
 pub mod feature_flags {
     pub const NONE: u32 = 0x00;
     pub const RAW: u32 = 0x01;
     pub const DEFAULT_DEVICE_CHANGE: u32 = 0x02;
     pub const MOVING_AVERAGE: u32 = 0x04;
+    pub const TEMPO: u32 = 0x08;
+    pub const ONSET: u32 = 0x10;
+    pub const BEAT: u32 = 0x20;
+    pub const PITCH: u32 = 0x40;
+    pub const SPECTRUM: u32 = 0x80;
+    pub const CHROMA: u32 = 0x100;
+    pub const KEY: u32 = 0x200;
+    pub const LOUDNESS: u32 = 0x400;
+    pub const LEVEL: u32 = 0x800;
+    pub const SILENCE: u32 = 0x1000;
+    pub const SPECTRAL_DESCRIPTORS: u32 = 0x2000;
+    pub const VOICE_ACTIVITY: u32 = 0x4000;
+    pub const BAND_ENERGY: u32 = 0x8000;
+    pub const CONSTANT_Q: u32 = 0x10000;
+    pub const NOTES: u32 = 0x20000;
 }
 
 #[derive(Debug, Clone)]
@@ -87,31 +567,142 @@ impl FeatureStore {
         }
     }
 
-    pub fn set_features(&mut self, features: &[Feature]) {
+    pub fn set_features(&mut self, features: &[Feature]) -> Result<(), AirapError> {
         self.store.clear();
         self.enabled_features = 0;
 
         for f in features.into_iter() {
+            f.validate()?;
             let flag = f.to_flag();
-            self.enabled_features |= flag | f.dependencies();
+            self.enabled_features |= flag;
             self.store.insert(flag, f.clone());
         }
 
-        for i in 1..self.enabled_features.ilog2() {
-            let flag = i.pow(2);
-            if self.enabled_features & flag > 0 && !self.store.contains_key(&flag) {
-                info!("Adding dependency with default settings ({flag})");
-                self.store.insert(flag, Feature::default(flag));
+        // dependencies can have dependencies of their own so keep going until nothing is missing
+        loop {
+            let dependencies = self
+                .store
+                .values()
+                .fold(feature_flags::NONE, |flags, f| flags | f.dependencies());
+            let missing = dependencies & !self.enabled_features;
+            if missing == 0 {
+                break;
+            }
+            for i in 0..u32::BITS {
+                let flag = 1 << i;
+                if missing & flag > 0 {
+                    info!("Adding dependency with default settings ({flag})");
+                    self.store.insert(flag, Feature::default(flag));
+                    self.enabled_features |= flag;
+                }
             }
         }
+
+        Ok(())
     }
 
     pub fn get(&self, flag: &u32) -> Option<&Feature> {
         self.store.get(flag)
     }
 
+    /// Every enabled feature including dependencies
+    pub fn features(&self) -> Vec<Feature> {
+        self.store.values().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
     #[inline]
     pub fn contains(&self, flag: u32) -> bool {
         self.enabled_features & flag > 0
     }
 }
+
+/// Test signals and a dispatcher running processors like the runner does
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::borrow::Cow;
+    use std::collections::VecDeque;
+    use std::f32::consts::TAU;
+
+    use pulse::sample::{Format, Spec};
+
+    use super::Processor;
+    use std::time::{Duration, SystemTime};
+
+    use crate::latency::{Instant, Latency};
+    use crate::{Event, RawEvent};
+
+    pub(crate) const RATE: u32 = 48000;
+    /// Frames in a raw event, about what a device delivers
+    const BLOCK: usize = 480;
+
+    pub(crate) fn spec() -> Spec {
+        Spec {
+            format: Format::F32le,
+            rate: RATE,
+            channels: 1,
+        }
+    }
+
+    /// `seconds` of a sine at `frequency` Hz with a peak of `amplitude`
+    pub(crate) fn sine(frequency: f32, amplitude: f32, seconds: f32) -> Vec<f32> {
+        (0..(seconds * RATE as f32) as usize)
+            // keep the phase small so it stays exact in f32
+            .map(|i| {
+                amplitude * ((i as f64 * frequency as f64 / RATE as f64).fract() as f32 * TAU).sin()
+            })
+            .collect()
+    }
+
+    /// `seconds` of short clicks at `bpm`
+    pub(crate) fn clicks(bpm: f32, seconds: f32) -> Vec<f32> {
+        let period = (60.0 * RATE as f32 / bpm) as usize;
+        (0..(seconds * RATE as f32) as usize)
+            .map(|i| if i % period < 32 { 0.9 } else { 0.0 })
+            .collect()
+    }
+
+    /// Events of the processors for mono `samples` arriving in blocks like from a device, every
+    /// processor is paired with the flags of its dependencies
+    pub(crate) fn run(
+        mut processors: Vec<(u32, Box<dyn Processor>)>,
+        samples: &[f32],
+    ) -> Vec<Event<'static>> {
+        let mut events = vec![];
+        for (i, block) in samples.chunks(BLOCK).enumerate() {
+            let mut queue = VecDeque::new();
+            // the audio starts at the unix epoch
+            let end = (i * BLOCK + block.len()) as f64 / RATE as f64;
+            let raw = Event::Raw(RawEvent {
+                data: Cow::Borrowed(block),
+                latency: Latency {
+                    internal: Instant::None,
+                    airap: Instant::None,
+                    captured: SystemTime::UNIX_EPOCH + Duration::from_secs_f64(end),
+                },
+            });
+            dispatch(&mut processors, &raw, &mut queue);
+            // every event reaches its dependents before the events they emit
+            while let Some(event) = queue.pop_front() {
+                dispatch(&mut processors, &event, &mut queue);
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    fn dispatch(
+        processors: &mut [(u32, Box<dyn Processor>)],
+        event: &Event,
+        queue: &mut VecDeque<Event<'static>>,
+    ) {
+        for (dependencies, processor) in processors.iter_mut() {
+            if *dependencies & event.to_flag() > 0 {
+                processor.process(event, &mut |e| queue.push_back(e));
+            }
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use log::warn;
+use pulse::sample::Spec;
+
+use crate::dsp::{smoothing_coefficient, Biquad};
+use crate::latency::Latency;
+use crate::Event;
+
+use super::Processor;
+
+/// How fast the normalization peak falls in dB per second
+const NORMALIZE_DECAY: f32 = 6.0;
+
+/// Equivalent noise bandwidth in bins of the hann window of the shared spectrum
+const HANN_BANDWIDTH: f32 = 1.5;
+
+/// Center frequencies of the ISO 266 third octave bands in Hz
+const THIRD_OCTAVES: [f32; 30] = [
+    25.0, 31.5, 40.0, 50.0, 63.0, 80.0, 100.0, 125.0, 160.0, 200.0, 250.0, 315.0, 400.0, 500.0,
+    630.0, 800.0, 1000.0, 1250.0, 1600.0, 2000.0, 2500.0, 3150.0, 4000.0, 5000.0, 6300.0, 8000.0,
+    10000.0, 12500.0, 16000.0, 20000.0,
+];
+
+/// Bands starting at or above the nyquist frequency of the device never have energy
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Band {
+    /// Lower edge in Hz
+    pub low: f32,
+    /// Upper edge in Hz
+    pub high: f32,
+    /// Rise time of the energy in milliseconds
+    pub attack: f32,
+    /// Fall time of the energy in milliseconds
+    pub release: f32,
+}
+
+impl Band {
+    pub fn new(low: f32, high: f32) -> Self {
+        Self {
+            low,
+            high,
+            attack: 10.0,
+            release: 200.0,
+        }
+    }
+
+    pub fn bass_mid_treble() -> Vec<Band> {
+        vec![
+            Band::new(20.0, 250.0),
+            Band::new(250.0, 4000.0),
+            Band::new(4000.0, 20000.0),
+        ]
+    }
+
+    pub fn third_octaves() -> Vec<Band> {
+        let edge = 2f32.powf(1.0 / 6.0);
+        THIRD_OCTAVES
+            .iter()
+            .map(|center| Band::new(center / edge, center * edge))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BandFilter {
+    /// Filter bank on the raw signal, `rate` is the amount of events per second
+    Iir { rate: f32 },
+    /// Sum the bins of the shared spectrum, an event for every spectrum
+    Fft,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BandEnergyEvent {
+    /// Smoothed energy of every band in dBFS
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub energy: Vec<f32>,
+    /// Smoothed energy of every band relative to its recent peak between 0 and 1
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub normalized: Vec<f32>,
+    pub latency: Latency,
+}
+
+struct BandState {
+    /// High and low pass, only when the edge is inside the audible range of the device
+    filters: (Option<Biquad>, Option<Biquad>),
+    /// The band starts at or above the nyquist frequency, nothing can pass
+    empty: bool,
+    attack: f32,
+    release: f32,
+    /// Sum of squares since the previous event
+    energy: f64,
+    power: f32,
+    peak: f32,
+}
+
+pub struct BandEnergy {
+    bands: Vec<Band>,
+    states: Vec<BandState>,
+    channels: usize,
+    /// Samples between events in iir mode
+    interval: usize,
+    since_emit: usize,
+    /// Peak decay factor per event
+    decay: f32,
+}
+
+impl BandEnergy {
+    /// `hop` is the time between spectra in seconds, only used for fft filtering
+    pub fn new(bands: &[Band], filter: BandFilter, hop: f32, spec: &Spec) -> Self {
+        let rate = spec.rate as f32;
+        let event_rate = match filter {
+            BandFilter::Iir { rate } => rate,
+            BandFilter::Fft => 1.0 / hop,
+        };
+        let iir = matches!(filter, BandFilter::Iir { .. });
+        let nyquist = rate / 2.0;
+        let empty = bands.iter().filter(|band| band.low >= nyquist).count();
+        if empty > 0 {
+            warn!("{empty} of {} bands are above {nyquist} Hz", bands.len());
+        }
+        let states = bands
+            .iter()
+            .map(|band| BandState {
+                filters: (
+                    (iir && band.low > 0.0 && band.low < nyquist)
+                        .then(|| Biquad::high_pass(band.low, rate)),
+                    (iir && band.high < nyquist).then(|| Biquad::low_pass(band.high, rate)),
+                ),
+                empty: band.low >= nyquist,
+                attack: smoothing_coefficient(band.attack, event_rate),
+                release: smoothing_coefficient(band.release, event_rate),
+                energy: 0.0,
+                power: 0.0,
+                peak: 0.0,
+            })
+            .collect();
+        Self {
+            bands: bands.to_vec(),
+            states,
+            channels: spec.channels as usize,
+            interval: ((rate / event_rate) as usize).max(1),
+            since_emit: 0,
+            decay: 10f32.powf(-NORMALIZE_DECAY / event_rate / 10.0),
+        }
+    }
+
+    /// Smooth the new power of every band and emit them
+    fn event(&mut self, powers: impl Iterator<Item = f32>, latency: &Latency) -> Event<'static> {
+        let mut energy = Vec::with_capacity(self.states.len());
+        let mut normalized = Vec::with_capacity(self.states.len());
+        for (state, power) in self.states.iter_mut().zip(powers) {
+            let coefficient = if power > state.power {
+                state.attack
+            } else {
+                state.release
+            };
+            state.power += coefficient * (power - state.power);
+            state.peak = (state.peak * self.decay).max(state.power);
+
+            energy.push(10.0 * state.power.log10());
+            normalized.push(if state.peak > 0.0 {
+                state.power / state.peak
+            } else {
+                0.0
+            });
+        }
+        Event::BandEnergy(BandEnergyEvent {
+            energy,
+            normalized,
+            latency: latency.clone(),
+        })
+    }
+}
+
+impl Processor for BandEnergy {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        match event {
+            Event::Raw(raw) => {
+                for frame in raw.data.chunks_exact(self.channels) {
+                    // filter the mix of all channels
+                    let x = frame.iter().sum::<f32>() / self.channels as f32;
+                    for state in self.states.iter_mut().filter(|s| !s.empty) {
+                        let (high_pass, low_pass) = &mut state.filters;
+                        let y = high_pass.as_mut().map_or(x, |f| f.process(x));
+                        let y = low_pass.as_mut().map_or(y, |f| f.process(y));
+                        state.energy += (y * y) as f64;
+                    }
+
+                    self.since_emit += 1;
+                    if self.since_emit >= self.interval {
+                        self.since_emit = 0;
+                        let interval = self.interval as f64;
+                        let powers: Vec<f32> = self
+                            .states
+                            .iter_mut()
+                            .map(|s| (std::mem::take(&mut s.energy) / interval) as f32)
+                            .collect();
+                        emit(self.event(powers.into_iter(), &raw.latency));
+                    }
+                }
+            }
+            Event::Spectrum(spectrum) => {
+                let resolution = spectrum.resolution;
+                let powers: Vec<f32> = self
+                    .bands
+                    .iter()
+                    .map(|band| {
+                        let low = (band.low / resolution).ceil() as usize;
+                        let high = ((band.high / resolution).ceil() as usize)
+                            .min(spectrum.magnitudes.len());
+                        // half the squared peak magnitude is the mean square of a sine, the window
+                        // spreads it over more than one bin
+                        spectrum.magnitudes[low.min(high)..high]
+                            .iter()
+                            .map(|m| m * m / 2.0)
+                            .sum::<f32>()
+                            / HANN_BANDWIDTH
+                    })
+                    .collect();
+                emit(self.event(powers.into_iter(), &spectrum.latency));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::feature_flags;
+    use crate::feature::spectrum::Spectrum;
+    use crate::feature::testing::{run, sine, spec, RATE};
+
+    fn energy(processors: Vec<(u32, Box<dyn Processor>)>) -> Vec<f32> {
+        let events = run(processors, &sine(1000.0, 0.5, 2.0));
+        match events.last() {
+            Some(Event::BandEnergy(band)) => band.energy.clone(),
+            event => panic!("{event:?}"),
+        }
+    }
+
+    fn bands() -> Vec<Band> {
+        vec![
+            Band::new(20.0, 250.0),
+            Band::new(250.0, 4000.0),
+            Band::new(8000.0, 16000.0),
+            // above the nyquist frequency
+            Band::new(30000.0, 40000.0),
+        ]
+    }
+
+    fn assert_tone_in_second_band(energy: &[f32]) {
+        // the mean square of the sine is 0.125
+        assert!((energy[1] + 9.03).abs() < 0.2, "{energy:?}");
+        // the filters fall 12 dB per octave
+        assert!(energy[0] < -30.0, "{energy:?}");
+        assert!(energy[2] < -30.0, "{energy:?}");
+        assert_eq!(energy[3], f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn tone_in_iir_band() {
+        let band = BandEnergy::new(&bands(), BandFilter::Iir { rate: 30.0 }, 0.0, &spec());
+        assert_tone_in_second_band(&energy(vec![(feature_flags::RAW, Box::new(band))]));
+    }
+
+    #[test]
+    fn tone_in_fft_band() {
+        let hop = 1024.0 / RATE as f32;
+        let band = BandEnergy::new(&bands(), BandFilter::Fft, hop, &spec());
+        assert_tone_in_second_band(&energy(vec![
+            (
+                feature_flags::RAW,
+                Box::new(Spectrum::new(4096, 1024, &spec())),
+            ),
+            (feature_flags::SPECTRUM, Box::new(band)),
+        ]));
+    }
+}
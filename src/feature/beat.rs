@@ -0,0 +1,250 @@
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+use pulse::sample::Spec;
+
+use crate::latency::{Instant, Latency};
+use crate::Event;
+
+use super::onset::OnsetEnvelope;
+use super::Processor;
+
+/// Amount of beat periods of onset envelope used for finding the phase
+const PERIODS: usize = 4;
+/// Weight of older periods when finding the phase
+const PERIOD_DECAY: f32 = 0.7;
+/// How much of the phase error gets corrected on every beat
+const CORRECTION: f32 = 0.3;
+/// How fast the accent of older bars is forgotten
+const ACCENT_DECAY: f32 = 0.9;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BeatEvent {
+    /// Amount of beats since listening started
+    pub index: u64,
+    /// Position of this beat in the bar where 0 is the downbeat
+    pub bar_position: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub bpm: f32,
+    /// Predicted wall clock time of the next beat, comparable with `latency.captured`
+    pub next: SystemTime,
+    pub latency: Latency,
+}
+
+pub struct Beat {
+    envelope: VecDeque<f32>,
+    capacity: usize,
+    envelope_rate: f32,
+    beats_per_bar: u32,
+    /// Amount of envelope values since listening started
+    position: u64,
+    /// Position at the end of the latest onset event, captured at `latency.captured`
+    end: u64,
+    /// Beat period in envelope values
+    period: Option<f32>,
+    bpm: f32,
+    next_beat: Option<f32>,
+    index: u64,
+    /// Accumulated onset strength for every position in the bar
+    accents: Vec<f32>,
+    latency: Latency,
+}
+
+impl Beat {
+    pub fn new(beats_per_bar: u32, min_bpm: f32, spec: &Spec) -> Self {
+        let envelope_rate = OnsetEnvelope::rate(spec);
+        let capacity = (PERIODS as f32 * 60.0 * envelope_rate / min_bpm).ceil() as usize + 1;
+        Self {
+            envelope: VecDeque::with_capacity(capacity),
+            capacity,
+            envelope_rate,
+            beats_per_bar,
+            position: 0,
+            end: 0,
+            period: None,
+            bpm: 0.0,
+            next_beat: None,
+            index: 0,
+            accents: vec![0.0; beats_per_bar as usize],
+            latency: Latency {
+                internal: Instant::None,
+                airap: Instant::None,
+                captured: SystemTime::UNIX_EPOCH,
+            },
+        }
+    }
+
+    /// Envelope values since the most recent beat, found by aligning a comb of `period` with the onsets
+    fn phase(&self, period: f32) -> usize {
+        let len = self.envelope.len();
+        (0..(period as usize).min(len))
+            .map(|offset| {
+                let mut weight = 1.0;
+                let mut score = 0.0;
+                for k in 0..PERIODS {
+                    let back = offset + (k as f32 * period).round() as usize;
+                    if back >= len {
+                        break;
+                    }
+                    score += weight * self.envelope[len - 1 - back];
+                    weight *= PERIOD_DECAY;
+                }
+                (offset, score)
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(offset, _)| offset)
+            .unwrap_or(0)
+    }
+
+    /// Onset strength around the most recent beat
+    fn accent(&self, phase: usize) -> f32 {
+        let len = self.envelope.len();
+        (phase.saturating_sub(2)..=phase + 2)
+            .filter(|back| *back < len)
+            .map(|back| self.envelope[len - 1 - back])
+            .fold(0.0, f32::max)
+    }
+
+    /// Envelope values to fire early so beats land on time after capture latency and onset detection delay
+    fn lead(&self, period: f32) -> f32 {
+        let latency = self.latency.internal.as_secs_f32() * self.envelope_rate;
+        (latency + 1.0).clamp(0.0, period * 0.5)
+    }
+
+    /// Wall clock time of envelope position `position`
+    fn time(&self, position: f32) -> SystemTime {
+        let offset = (position - self.end as f32) / self.envelope_rate;
+        if offset >= 0.0 {
+            self.latency.captured + Duration::from_secs_f32(offset)
+        } else {
+            self.latency.captured - Duration::from_secs_f32(-offset)
+        }
+    }
+
+    fn on_envelope(&mut self, emit: &mut dyn FnMut(Event<'static>)) {
+        let Some(period) = self.period else {
+            return;
+        };
+        let now = self.position as f32;
+        let next_beat = match self.next_beat {
+            // resync when the schedule fell behind, eg. after a tempo change
+            Some(next_beat) if now - next_beat < period => next_beat,
+            _ => {
+                let next_beat = now - self.phase(period) as f32 + period;
+                self.next_beat = Some(next_beat);
+                next_beat
+            }
+        };
+        if now + self.lead(period) < next_beat {
+            return;
+        }
+
+        let phase = self.phase(period);
+        let accent = self.accent(phase);
+        let slot = (self.index % self.beats_per_bar as u64) as usize;
+        for (i, a) in self.accents.iter_mut().enumerate() {
+            *a *= ACCENT_DECAY;
+            if i == slot {
+                *a += accent;
+            }
+        }
+        let downbeat = self
+            .accents
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        let bar_position =
+            (slot + self.beats_per_bar as usize - downbeat) as u32 % self.beats_per_bar;
+
+        // pull the schedule towards the phase of the onsets
+        let scheduled = next_beat + period;
+        let measured = now - phase as f32 + period;
+        let mut error = (measured - scheduled) % period;
+        if error > period / 2.0 {
+            error -= period;
+        } else if error < -period / 2.0 {
+            error += period;
+        }
+        let next = next_beat + period + CORRECTION * error;
+        self.next_beat = Some(next);
+
+        emit(Event::Beat(BeatEvent {
+            index: self.index,
+            bar_position,
+            bpm: self.bpm,
+            next: self.time(next),
+            latency: self.latency.clone(),
+        }));
+        self.index += 1;
+    }
+}
+
+impl Processor for Beat {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        match event {
+            Event::Tempo(tempo) => {
+                self.bpm = tempo.bpm;
+                self.period = Some(60.0 * self.envelope_rate / tempo.bpm);
+            }
+            Event::Onset(onset) => {
+                self.latency = onset.latency.clone();
+                self.end = self.position + onset.envelope.len() as u64;
+                for v in onset.envelope.iter() {
+                    if self.envelope.len() == self.capacity {
+                        self.envelope.pop_front();
+                    }
+                    self.envelope.push_back(*v);
+                    self.position += 1;
+                    self.on_envelope(emit);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::feature_flags;
+    use crate::feature::onset::Onset;
+    use crate::feature::tempo::Tempo;
+    use crate::feature::testing::{clicks, run, spec};
+
+    #[test]
+    fn beats_of_clicks() {
+        let events = run(
+            vec![
+                (feature_flags::RAW, Box::new(Onset::default())),
+                (
+                    feature_flags::ONSET,
+                    Box::new(Tempo::new(60.0, 180.0, &spec())),
+                ),
+                (
+                    feature_flags::ONSET | feature_flags::TEMPO,
+                    Box::new(Beat::new(4, 60.0, &spec())),
+                ),
+            ],
+            &clicks(120.0, 12.0),
+        );
+        let beats: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Beat(beat) => Some(beat),
+                _ => None,
+            })
+            .collect();
+        assert!(beats.len() > 10, "{beats:?}");
+        for beat in beats.iter().skip(4) {
+            assert!((beat.bpm - 120.0).abs() < 1.0, "{beat:?}");
+            // the clicks start at the unix epoch so the next beat lands on a multiple of 0.5s,
+            // detecting an onset takes up to a hop of the envelope
+            let next = beat.next.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+            let phase = next.as_secs_f32() % 0.5;
+            assert!(phase.min(0.5 - phase) < 0.03, "{beat:?}");
+        }
+    }
+}
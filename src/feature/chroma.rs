@@ -0,0 +1,259 @@
+use crate::dsp::frequency_to_midi;
+use crate::latency::Latency;
+use crate::Event;
+
+use super::constant_q::ConstantQEvent;
+use super::Processor;
+
+/// Krumhansl-Kessler key profiles starting at the tonic
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChromaEvent {
+    /// Energy of every pitch class starting at C, normalized so the strongest class is 1
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub chroma: [f32; 12],
+    pub latency: Latency,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyEvent {
+    /// Pitch class of the tonic where 0 is C
+    pub tonic: u8,
+    pub mode: Mode,
+    /// Correlation with the key profile between -1 and 1
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub confidence: f32,
+    pub latency: Latency,
+}
+
+/// Folds the constant q bins into the 12 pitch classes
+#[derive(Default)]
+pub struct Chroma {
+    /// Pitch class of every constant q bin, cached for the current layout
+    classes: Vec<usize>,
+    min_frequency: f32,
+}
+
+impl Chroma {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn classes(&mut self, constant_q: &ConstantQEvent) -> &[usize] {
+        if self.classes.len() != constant_q.magnitudes.len()
+            || self.min_frequency != constant_q.min_frequency
+        {
+            self.min_frequency = constant_q.min_frequency;
+            self.classes = (0..constant_q.magnitudes.len())
+                .map(|k| frequency_to_midi(constant_q.frequency(k)).round() as usize % 12)
+                .collect();
+        }
+        &self.classes
+    }
+}
+
+impl Processor for Chroma {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::ConstantQ(constant_q) = event else {
+            return;
+        };
+
+        let mut chroma = [0.0; 12];
+        let classes = self.classes(constant_q);
+        for (class, magnitude) in classes.iter().zip(&constant_q.magnitudes) {
+            chroma[*class] += magnitude * magnitude;
+        }
+        let max = chroma.iter().cloned().fold(0.0, f32::max);
+        if max > 0.0 {
+            chroma.iter_mut().for_each(|c| *c /= max);
+        }
+
+        emit(Event::Chroma(ChromaEvent {
+            chroma,
+            latency: constant_q.latency.clone(),
+        }));
+    }
+}
+
+/// Pearson correlation of two pitch class profiles, `b` rotated by `shift`
+fn correlation(a: &[f32; 12], b: &[f32; 12], shift: usize) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / 12.0;
+    let mean_b = b.iter().sum::<f32>() / 12.0;
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for i in 0..12 {
+        let x = a[i] - mean_a;
+        let y = b[(i + 12 - shift) % 12] - mean_b;
+        covariance += x * y;
+        variance_a += x * x;
+        variance_b += y * y;
+    }
+    let denominator = (variance_a * variance_b).sqrt();
+    if denominator > 0.0 {
+        covariance / denominator
+    } else {
+        0.0
+    }
+}
+
+/// Matches the averaged chroma with major and minor key profiles
+pub struct Key {
+    profile: [f32; 12],
+    /// Weight of a new chroma in the running average
+    smoothing: f32,
+    /// Amount of consecutive chroma events a new key has to win before it is reported
+    stability: usize,
+    current: Option<(u8, Mode)>,
+    candidate: Option<((u8, Mode), usize)>,
+}
+
+impl Key {
+    /// `window` and `stability` are in seconds, `hop` is the time between chroma events
+    pub fn new(window: f32, stability: f32, hop: f32) -> Self {
+        Self {
+            profile: [0.0; 12],
+            smoothing: (hop / window).clamp(0.0, 1.0),
+            stability: ((stability / hop).ceil() as usize).max(1),
+            current: None,
+            candidate: None,
+        }
+    }
+
+    fn estimate(&self) -> ((u8, Mode), f32) {
+        let mut best = ((0, Mode::Major), f32::MIN);
+        for tonic in 0..12 {
+            for (mode, profile) in [(Mode::Major, &MAJOR_PROFILE), (Mode::Minor, &MINOR_PROFILE)] {
+                let r = correlation(&self.profile, profile, tonic);
+                if r > best.1 {
+                    best = ((tonic as u8, mode), r);
+                }
+            }
+        }
+        best
+    }
+}
+
+impl Processor for Key {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::Chroma(chroma) = event else {
+            return;
+        };
+
+        for (p, c) in self.profile.iter_mut().zip(chroma.chroma) {
+            *p += self.smoothing * (c - *p);
+        }
+        if self.profile.iter().all(|p| *p <= f32::EPSILON) {
+            return;
+        }
+
+        let (key, confidence) = self.estimate();
+        if self.current == Some(key) {
+            self.candidate = None;
+            return;
+        }
+        let count = match self.candidate {
+            Some((candidate, count)) if candidate == key => count + 1,
+            _ => 1,
+        };
+        if count < self.stability {
+            self.candidate = Some((key, count));
+            return;
+        }
+
+        self.candidate = None;
+        self.current = Some(key);
+        emit(Event::Key(KeyEvent {
+            tonic: key.0,
+            mode: key.1,
+            confidence,
+            latency: chroma.latency.clone(),
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::constant_q::ConstantQ;
+    use crate::feature::feature_flags;
+    use crate::feature::spectrum::Spectrum;
+    use crate::feature::testing::{run, sine, spec, RATE};
+
+    fn chroma_and_key(samples: &[f32]) -> Vec<Event<'static>> {
+        let hop = 1024.0 / RATE as f32;
+        run(
+            vec![
+                (
+                    feature_flags::RAW,
+                    Box::new(Spectrum::new(4096, 1024, &spec())),
+                ),
+                (
+                    feature_flags::SPECTRUM,
+                    Box::new(ConstantQ::new(36, 55.0, 7, RATE as f32 / 4096.0, 2049)),
+                ),
+                (feature_flags::CONSTANT_Q, Box::new(Chroma::new())),
+                (feature_flags::CHROMA, Box::new(Key::new(2.0, 0.5, hop))),
+            ],
+            samples,
+        )
+    }
+
+    #[test]
+    fn a_of_sine() {
+        let events = chroma_and_key(&sine(440.0, 0.5, 1.0));
+        let spectrum = events
+            .iter()
+            .rev()
+            .find_map(|e| match e {
+                Event::Spectrum(spectrum) => Some(spectrum),
+                _ => None,
+            })
+            .unwrap();
+        let peak = (0..spectrum.magnitudes.len())
+            .max_by(|a, b| spectrum.magnitudes[*a].total_cmp(&spectrum.magnitudes[*b]))
+            .unwrap();
+        let frequency = peak as f32 * spectrum.resolution;
+        assert!(
+            (frequency - 440.0).abs() <= spectrum.resolution,
+            "{frequency}"
+        );
+
+        let Some(Event::Chroma(chroma)) =
+            events.iter().rev().find(|e| matches!(e, Event::Chroma(_)))
+        else {
+            panic!("no chroma");
+        };
+        assert_eq!(chroma.chroma[9], 1.0, "{chroma:?}");
+        for (class, energy) in chroma.chroma.iter().enumerate() {
+            assert!(class == 9 || *energy < 0.2, "{chroma:?}");
+        }
+    }
+
+    #[test]
+    fn c_major_of_triad() {
+        let samples: Vec<f32> = [261.63, 329.63, 392.0]
+            .iter()
+            .map(|f| sine(*f, 0.2, 4.0))
+            .reduce(|a, b| a.iter().zip(b).map(|(a, b)| a + b).collect())
+            .unwrap();
+        let events = chroma_and_key(&samples);
+        let Some(Event::Key(key)) = events.iter().rev().find(|e| matches!(e, Event::Key(_))) else {
+            panic!("no key");
+        };
+        assert_eq!((key.tonic, key.mode), (0, Mode::Major), "{key:?}");
+    }
+}
@@ -0,0 +1,184 @@
+use log::warn;
+
+use crate::latency::Latency;
+use crate::Event;
+
+use super::Processor;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConstantQEvent {
+    /// Magnitude of every bin starting at `min_frequency`, averaged over the spectrum bins it covers
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub magnitudes: Vec<f32>,
+    /// Center frequency of the first bin in Hz
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub min_frequency: f32,
+    pub bins_per_octave: u32,
+    pub latency: Latency,
+}
+
+impl ConstantQEvent {
+    /// Center frequency of bin `k` in Hz
+    pub fn frequency(&self, k: usize) -> f32 {
+        self.min_frequency * 2f32.powf(k as f32 / self.bins_per_octave as f32)
+    }
+}
+
+/// Weights of the spectrum bins making up a single constant q bin
+#[derive(Debug, Clone)]
+struct Kernel {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+/// Constant q transform from the shared spectrum using a kernel per bin (Brown & Puckette, 1992)
+pub struct ConstantQ {
+    min_frequency: f32,
+    bins_per_octave: u32,
+    kernels: Vec<Kernel>,
+}
+
+impl ConstantQ {
+    /// `resolution` is the width of a spectrum bin and `bins` the amount of bins in a spectrum.
+    /// Bins at or above the nyquist frequency of the spectrum are left out, the spectrum has no
+    /// energy to give them.
+    pub fn new(
+        bins_per_octave: u32,
+        min_frequency: f32,
+        octaves: u32,
+        resolution: f32,
+        bins: usize,
+    ) -> Self {
+        let b = bins_per_octave as f32;
+        let nyquist = resolution * bins.saturating_sub(1) as f32;
+        let kernels: Vec<Kernel> = (0..bins_per_octave * octaves)
+            .map(|k| min_frequency * 2f32.powf(k as f32 / b))
+            .take_while(|center| *center < nyquist)
+            .map(|center| {
+                // triangle in log frequency reaching zero at the neighbouring bins
+                let low = ((center * 2f32.powf(-1.0 / b) / resolution).floor() as usize).min(bins);
+                let high =
+                    ((center * 2f32.powf(1.0 / b) / resolution).ceil() as usize + 1).min(bins);
+                let mut weights: Vec<f32> = (low..high)
+                    .map(|i| {
+                        let frequency = i as f32 * resolution;
+                        if frequency <= 0.0 {
+                            return 0.0;
+                        }
+                        (1.0 - (frequency / center).log2().abs() * b).max(0.0)
+                    })
+                    .collect();
+
+                // no spectrum bin close enough, interpolate between the nearest two instead
+                if weights.iter().sum::<f32>() <= 0.0 {
+                    let position = center / resolution;
+                    let fraction = position.fract();
+                    let start = (position.floor() as usize).min(bins.saturating_sub(2));
+                    return Kernel {
+                        start,
+                        weights: vec![1.0 - fraction, fraction],
+                    };
+                }
+
+                let sum: f32 = weights.iter().sum();
+                weights.iter_mut().for_each(|w| *w /= sum);
+                Kernel {
+                    start: low,
+                    weights,
+                }
+            })
+            .collect();
+        if kernels.len() < (bins_per_octave * octaves) as usize {
+            warn!(
+                "Constant q has {} of {} bins, the others are above {nyquist} Hz",
+                kernels.len(),
+                bins_per_octave * octaves
+            );
+        }
+
+        Self {
+            min_frequency,
+            bins_per_octave,
+            kernels,
+        }
+    }
+}
+
+impl Processor for ConstantQ {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::Spectrum(spectrum) = event else {
+            return;
+        };
+
+        let magnitudes = self
+            .kernels
+            .iter()
+            .map(|kernel| {
+                spectrum.magnitudes[kernel.start..]
+                    .iter()
+                    .zip(&kernel.weights)
+                    .map(|(m, w)| m * w)
+                    .sum()
+            })
+            .collect();
+
+        emit(Event::ConstantQ(ConstantQEvent {
+            magnitudes,
+            min_frequency: self.min_frequency,
+            bins_per_octave: self.bins_per_octave,
+            latency: spectrum.latency.clone(),
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::feature_flags;
+    use crate::feature::spectrum::Spectrum;
+    use crate::feature::testing::{run, sine, spec, RATE};
+
+    fn constant_q(octaves: u32) -> Vec<ConstantQEvent> {
+        let resolution = RATE as f32 / 4096.0;
+        run(
+            vec![
+                (
+                    feature_flags::RAW,
+                    Box::new(Spectrum::new(4096, 1024, &spec())),
+                ),
+                (
+                    feature_flags::SPECTRUM,
+                    Box::new(ConstantQ::new(12, 55.0, octaves, resolution, 2049)),
+                ),
+            ],
+            &sine(440.0, 0.5, 1.0),
+        )
+        .into_iter()
+        .filter_map(|e| match e {
+            Event::ConstantQ(constant_q) => Some(constant_q),
+            _ => None,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn peak_of_sine() {
+        let events = constant_q(6);
+        let event = events.last().unwrap();
+        assert_eq!(event.magnitudes.len(), 72);
+        let peak = (0..event.magnitudes.len())
+            .max_by(|a, b| event.magnitudes[*a].total_cmp(&event.magnitudes[*b]))
+            .unwrap();
+        // three octaves above 55Hz
+        assert_eq!(peak, 36);
+        assert!((event.frequency(peak) - 440.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn bins_above_nyquist_left_out() {
+        // 55Hz * 2^(106 / 12) is the first bin above the nyquist frequency of 24kHz
+        let events = constant_q(10);
+        assert_eq!(events.last().unwrap().magnitudes.len(), 106);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::latency::Latency;
+use crate::Event;
+
+use super::Processor;
+
+/// Upper edge in Hz of the lowest contrast band, every next band is an octave higher
+const CONTRAST_BASE: f32 = 200.0;
+const CONTRAST_BANDS: usize = 6;
+/// Fraction of a band used for its peak and valley
+const CONTRAST_QUANTILE: f32 = 0.02;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpectralDescriptorsEvent {
+    /// Center of mass of the spectrum in Hz
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub centroid: f32,
+    /// Standard deviation around the centroid in Hz
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub spread: f32,
+    /// Frequency in Hz below which the configured part of the energy lies
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub rolloff: f32,
+    /// Geometric mean divided by arithmetic mean of the power, 1 for noise and 0 for a pure tone
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub flatness: f32,
+    /// Positive change in magnitude since the previous spectrum
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub flux: f32,
+    /// Sign changes per sample since the previous spectrum
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub zero_crossing_rate: f32,
+    /// Difference between peaks and valleys in dB for every octave band starting below 200Hz
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub contrast: Vec<f32>,
+    pub latency: Latency,
+}
+
+pub struct SpectralDescriptors {
+    rolloff: f32,
+    previous: Vec<f32>,
+    last_sample: f32,
+    crossings: usize,
+    samples: usize,
+}
+
+impl SpectralDescriptors {
+    /// `rolloff` is the part of the energy between 0 and 1 below the rolloff frequency
+    pub fn new(rolloff: f32) -> Self {
+        Self {
+            rolloff,
+            previous: vec![],
+            last_sample: 0.0,
+            crossings: 0,
+            samples: 0,
+        }
+    }
+
+    /// Peak to valley ratio of every octave band
+    fn contrast(magnitudes: &[f32], resolution: f32) -> Vec<f32> {
+        let mut edges: Vec<usize> = (0..CONTRAST_BANDS)
+            .map(|i| {
+                ((CONTRAST_BASE * 2f32.powi(i as i32) / resolution) as usize).min(magnitudes.len())
+            })
+            .collect();
+        edges.insert(0, 1);
+        edges.push(magnitudes.len());
+
+        edges
+            .windows(2)
+            .map(|edge| {
+                let mut band = magnitudes[edge[0]..edge[1].max(edge[0])].to_vec();
+                if band.is_empty() {
+                    return 0.0;
+                }
+                band.sort_by(f32::total_cmp);
+                let n = ((band.len() as f32 * CONTRAST_QUANTILE).round() as usize).max(1);
+                let valley = band[..n].iter().sum::<f32>() / n as f32;
+                let peak = band[band.len() - n..].iter().sum::<f32>() / n as f32;
+                20.0 * ((peak + f32::EPSILON) / (valley + f32::EPSILON)).log10()
+            })
+            .collect()
+    }
+}
+
+impl Processor for SpectralDescriptors {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        match event {
+            Event::Raw(raw) => {
+                for s in raw.data.iter() {
+                    if (*s >= 0.0) != (self.last_sample >= 0.0) {
+                        self.crossings += 1;
+                    }
+                    self.last_sample = *s;
+                }
+                self.samples += raw.data.len();
+            }
+            Event::Spectrum(spectrum) => {
+                let magnitudes = &spectrum.magnitudes;
+                let frequency = |i: usize| spectrum.frequency(i);
+
+                let total: f32 = magnitudes.iter().sum();
+                let centroid = if total > 0.0 {
+                    magnitudes
+                        .iter()
+                        .enumerate()
+                        .map(|(i, m)| frequency(i) * m)
+                        .sum::<f32>()
+                        / total
+                } else {
+                    0.0
+                };
+                let spread = if total > 0.0 {
+                    (magnitudes
+                        .iter()
+                        .enumerate()
+                        .map(|(i, m)| (frequency(i) - centroid).powi(2) * m)
+                        .sum::<f32>()
+                        / total)
+                        .sqrt()
+                } else {
+                    0.0
+                };
+
+                let energy: f32 = magnitudes.iter().map(|m| m * m).sum();
+                let mut cumulative = 0.0;
+                let rolloff = magnitudes
+                    .iter()
+                    .position(|m| {
+                        cumulative += m * m;
+                        cumulative >= self.rolloff * energy
+                    })
+                    .map(frequency)
+                    .unwrap_or(0.0);
+
+                // skip the dc bin, it says nothing about the timbre
+                let power = magnitudes[1..].iter().map(|m| m * m + f32::EPSILON);
+                let n = (magnitudes.len() - 1) as f32;
+                let geometric = (power.clone().map(f32::ln).sum::<f32>() / n).exp();
+                let arithmetic = power.sum::<f32>() / n;
+                let flatness = geometric / arithmetic;
+
+                let flux = if self.previous.len() == magnitudes.len() {
+                    magnitudes
+                        .iter()
+                        .zip(&self.previous)
+                        .map(|(m, p)| (m - p).max(0.0).powi(2))
+                        .sum::<f32>()
+                        .sqrt()
+                } else {
+                    0.0
+                };
+                self.previous.clear();
+                self.previous.extend_from_slice(magnitudes);
+
+                let zero_crossing_rate = if self.samples > 0 {
+                    self.crossings as f32 / self.samples as f32
+                } else {
+                    0.0
+                };
+                self.crossings = 0;
+                self.samples = 0;
+
+                emit(Event::SpectralDescriptors(SpectralDescriptorsEvent {
+                    centroid,
+                    spread,
+                    rolloff,
+                    flatness,
+                    flux,
+                    zero_crossing_rate,
+                    contrast: Self::contrast(magnitudes, spectrum.resolution),
+                    latency: spectrum.latency.clone(),
+                }));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::feature_flags;
+    use crate::feature::spectrum::Spectrum;
+    use crate::feature::testing::{run, sine, spec};
+
+    #[test]
+    fn descriptors_of_sine() {
+        let events = run(
+            vec![
+                (
+                    feature_flags::RAW,
+                    Box::new(Spectrum::new(4096, 1024, &spec())),
+                ),
+                (
+                    feature_flags::RAW | feature_flags::SPECTRUM,
+                    Box::new(SpectralDescriptors::new(0.85)),
+                ),
+            ],
+            &sine(1000.0, 0.5, 1.0),
+        );
+        let descriptors = events
+            .iter()
+            .rev()
+            .find_map(|e| match e {
+                Event::SpectralDescriptors(descriptors) => Some(descriptors),
+                _ => None,
+            })
+            .unwrap();
+        assert!(
+            (descriptors.centroid - 1000.0).abs() < 20.0,
+            "{descriptors:?}"
+        );
+        assert!(
+            (descriptors.rolloff - 1000.0).abs() < 20.0,
+            "{descriptors:?}"
+        );
+        assert!(descriptors.flatness < 0.1, "{descriptors:?}");
+        // a sine crosses zero twice per period
+        let expected = 2.0 * 1000.0 / 48000.0;
+        assert!(
+            (descriptors.zero_crossing_rate - expected).abs() < 0.001,
+            "{descriptors:?}"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use pulse::sample::Spec;
+
+use crate::dsp::{amplitude_to_db, smoothing_coefficient};
+use crate::latency::Latency;
+use crate::Event;
+
+use super::Processor;
+
+/// Samples at or above this amplitude count as clipped
+const CLIP: f32 = 0.999;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LevelEvent {
+    /// Rms of every channel after ballistics in dBFS
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub rms: Vec<f32>,
+    /// Highest sample of every channel since the previous event in dBFS
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub peak: Vec<f32>,
+    /// Held peak of every channel in dBFS
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub peak_hold: Vec<f32>,
+    /// Difference between `peak` and `rms` in dB
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub crest_factor: Vec<f32>,
+    /// Amount of clipped samples of every channel since listening started
+    pub clips: Vec<u64>,
+    pub latency: Latency,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Meter {
+    power: f32,
+    /// Sum of squared samples since the previous event
+    sum: f32,
+    peak: f32,
+    hold: f32,
+    /// Samples left before the held peak starts to decay
+    hold_left: usize,
+    clips: u64,
+}
+
+pub struct Level {
+    channels: usize,
+    /// Ballistics of the power, applied once per event to the mean power since the previous event
+    attack: f32,
+    release: f32,
+    /// Per sample decay of the held peak
+    decay: f32,
+    hold: usize,
+    meters: Vec<Meter>,
+    interval: usize,
+    since_emit: usize,
+}
+
+impl Level {
+    /// `attack`, `release` and `hold` are in milliseconds, `rate` is the amount of events per second
+    pub fn new(attack: f32, release: f32, hold: f32, rate: f32, spec: &Spec) -> Self {
+        let sample_rate = spec.rate as f32;
+        let channels = spec.channels as usize;
+        let interval = ((sample_rate / rate) as usize).max(1);
+        let event_rate = sample_rate / interval as f32;
+        Self {
+            channels,
+            attack: smoothing_coefficient(attack, event_rate),
+            release: smoothing_coefficient(release, event_rate),
+            decay: smoothing_coefficient(release, sample_rate),
+            hold: (hold * sample_rate / 1000.0) as usize,
+            meters: vec![Meter::default(); channels],
+            interval,
+            since_emit: 0,
+        }
+    }
+
+    fn event(&mut self, latency: &Latency) -> LevelEvent {
+        let mut event = LevelEvent {
+            rms: Vec::with_capacity(self.channels),
+            peak: Vec::with_capacity(self.channels),
+            peak_hold: Vec::with_capacity(self.channels),
+            crest_factor: Vec::with_capacity(self.channels),
+            clips: Vec::with_capacity(self.channels),
+            latency: latency.clone(),
+        };
+        for meter in self.meters.iter_mut() {
+            let power = meter.sum / self.interval as f32;
+            let coefficient = if power > meter.power {
+                self.attack
+            } else {
+                self.release
+            };
+            meter.power += coefficient * (power - meter.power);
+            meter.sum = 0.0;
+
+            let rms = meter.power.sqrt();
+            event.rms.push(amplitude_to_db(rms));
+            event.peak.push(amplitude_to_db(meter.peak));
+            event.peak_hold.push(amplitude_to_db(meter.hold));
+            event.crest_factor.push(if rms > 0.0 {
+                amplitude_to_db(meter.peak / rms)
+            } else {
+                0.0
+            });
+            event.clips.push(meter.clips);
+            meter.peak = 0.0;
+        }
+        event
+    }
+}
+
+impl Processor for Level {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::Raw(raw) = event else {
+            return;
+        };
+
+        for frame in raw.data.chunks_exact(self.channels) {
+            for (meter, x) in self.meters.iter_mut().zip(frame) {
+                meter.sum += x * x;
+
+                let amplitude = x.abs();
+                meter.peak = meter.peak.max(amplitude);
+                if amplitude >= meter.hold {
+                    meter.hold = amplitude;
+                    meter.hold_left = self.hold;
+                } else if meter.hold_left > 0 {
+                    meter.hold_left -= 1;
+                } else {
+                    meter.hold -= self.decay * meter.hold;
+                }
+
+                if amplitude >= CLIP {
+                    meter.clips += 1;
+                }
+            }
+
+            self.since_emit += 1;
+            if self.since_emit >= self.interval {
+                self.since_emit = 0;
+                emit(Event::Level(self.event(&raw.latency)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::feature_flags;
+    use crate::feature::testing::{run, sine, spec};
+
+    fn level(samples: &[f32]) -> LevelEvent {
+        let level = Level::new(10.0, 300.0, 1500.0, 30.0, &spec());
+        let events = run(vec![(feature_flags::RAW, Box::new(level))], samples);
+        match events.last() {
+            Some(Event::Level(level)) => level.clone(),
+            event => panic!("{event:?}"),
+        }
+    }
+
+    #[test]
+    fn rms_and_peak_of_sine() {
+        // a sine with a peak of 0.5 has an rms of 0.5 / sqrt(2)
+        let level = level(&sine(1000.0, 0.5, 2.0));
+        assert!((level.rms[0] + 9.03).abs() < 0.1, "{level:?}");
+        assert!((level.peak[0] + 6.02).abs() < 0.1, "{level:?}");
+        assert!((level.peak_hold[0] + 6.02).abs() < 0.1, "{level:?}");
+        assert!((level.crest_factor[0] - 3.01).abs() < 0.1, "{level:?}");
+        assert_eq!(level.clips, [0]);
+    }
+
+    #[test]
+    fn clipped_samples() {
+        let mut samples = sine(1000.0, 0.5, 1.0);
+        samples[1000..1010].fill(1.0);
+        samples[2000..2005].fill(-1.0);
+        assert_eq!(level(&samples).clips, [15]);
+    }
+}
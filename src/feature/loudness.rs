@@ -0,0 +1,444 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use pulse::sample::Spec;
+
+use crate::dsp::Biquad;
+use crate::latency::Latency;
+use crate::Event;
+
+use super::Processor;
+
+/// Gating blocks are measured in steps of 100ms
+const STEPS_PER_SECOND: usize = 10;
+const MOMENTARY_STEPS: usize = 4;
+const SHORT_TERM_STEPS: usize = 30;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+/// Gating blocks are counted in bins of 0.1 LU from the absolute gate up to +30 LUFS
+const HISTOGRAM_STEP: f64 = 0.1;
+const HISTOGRAM_BINS: usize = 1000;
+
+/// Polyphase interpolation filter for 4x oversampling from ITU-R BS.1770-4 annex 2
+const TRUE_PEAK_PHASES: [[f64; 12]; 4] = [
+    [
+        0.001708984375,
+        0.010986328125,
+        -0.0196533203125,
+        0.033203125,
+        -0.0594482421875,
+        0.1373291015625,
+        0.97216796875,
+        -0.102294921875,
+        0.047607421875,
+        -0.026611328125,
+        0.014892578125,
+        -0.00830078125,
+    ],
+    [
+        -0.0291748046875,
+        0.029296875,
+        -0.0517578125,
+        0.089111328125,
+        -0.16650390625,
+        0.465087890625,
+        0.77978515625,
+        -0.2003173828125,
+        0.1015625,
+        -0.0582275390625,
+        0.0330810546875,
+        -0.0189208984375,
+    ],
+    [
+        -0.0189208984375,
+        0.0330810546875,
+        -0.0582275390625,
+        0.1015625,
+        -0.2003173828125,
+        0.77978515625,
+        0.465087890625,
+        -0.16650390625,
+        0.089111328125,
+        -0.0517578125,
+        0.029296875,
+        -0.0291748046875,
+    ],
+    [
+        -0.00830078125,
+        0.014892578125,
+        -0.026611328125,
+        0.047607421875,
+        -0.102294921875,
+        0.97216796875,
+        0.1373291015625,
+        -0.0594482421875,
+        0.033203125,
+        -0.0196533203125,
+        0.010986328125,
+        0.001708984375,
+    ],
+];
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LoudnessEvent {
+    /// Loudness of the last 400ms in LUFS
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub momentary: f32,
+    /// Loudness of the last 3s in LUFS
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub short_term: f32,
+    /// Gated loudness since the start or last reset in LUFS
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub integrated: f32,
+    /// Loudness range (LRA) since the start or last reset in LU
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub range: f32,
+    /// Highest true peak of every channel since the start or last reset in dBTP
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub true_peak: Vec<f32>,
+    pub latency: Latency,
+}
+
+/// Restarts the integrated loudness, loudness range and true peak measurements
+#[derive(Debug, Clone, Default)]
+pub struct LoudnessReset(Arc<AtomicBool>);
+
+impl LoudnessReset {
+    pub fn reset(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// K-weighting filter from ITU-R BS.1770 for any sample rate
+fn k_weighting(rate: f64) -> [Biquad; 2] {
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+/// Loudness in LUFS of a mean square power
+fn lufs(power: f64) -> f64 {
+    if power <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    -0.691 + 10.0 * power.log10()
+}
+
+/// Loudness of the gating blocks, like libebur128 the memory and the time to gate them stay the
+/// same however long the measurement runs. Blocks count with the loudness of the center of
+/// their bin.
+#[derive(Debug, Clone)]
+struct Histogram {
+    counts: Vec<u64>,
+    /// Loudness of the center of every bin
+    loudness: Vec<f64>,
+    /// Power of the center of every bin
+    power: Vec<f64>,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        let loudness: Vec<f64> = (0..HISTOGRAM_BINS)
+            .map(|i| ABSOLUTE_GATE + (i as f64 + 0.5) * HISTOGRAM_STEP)
+            .collect();
+        Self {
+            counts: vec![0; HISTOGRAM_BINS],
+            power: loudness
+                .iter()
+                .map(|l| 10f64.powf((l + 0.691) / 10.0))
+                .collect(),
+            loudness,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.counts.iter_mut().for_each(|c| *c = 0);
+    }
+
+    /// Count a block, blocks at or below the absolute gate are dropped
+    fn add(&mut self, power: f64) {
+        let loudness = lufs(power);
+        if loudness <= ABSOLUTE_GATE {
+            return;
+        }
+        let bin = ((loudness - ABSOLUTE_GATE) / HISTOGRAM_STEP) as usize;
+        self.counts[bin.min(HISTOGRAM_BINS - 1)] += 1;
+    }
+
+    /// Bins of the blocks above the absolute gate and `relative` LU below their loudness
+    fn gated(&self, relative: f64) -> impl Iterator<Item = usize> + '_ {
+        let (count, energy) = (0..HISTOGRAM_BINS).fold((0, 0.0), |(count, energy), i| {
+            (
+                count + self.counts[i],
+                energy + self.counts[i] as f64 * self.power[i],
+            )
+        });
+        let threshold = match count {
+            0 => f64::INFINITY,
+            _ => lufs(energy / count as f64) + relative,
+        };
+        (0..HISTOGRAM_BINS).filter(move |i| self.counts[*i] > 0 && self.loudness[*i] > threshold)
+    }
+
+    /// Loudness of the mean power of the gated blocks
+    fn integrated(&self) -> f64 {
+        let (count, energy) = self
+            .gated(RELATIVE_GATE)
+            .fold((0, 0.0), |(count, energy), i| {
+                (
+                    count + self.counts[i],
+                    energy + self.counts[i] as f64 * self.power[i],
+                )
+            });
+        match count {
+            0 => f64::NEG_INFINITY,
+            _ => lufs(energy / count as f64),
+        }
+    }
+
+    /// Difference between the 10th and 95th percentile of the loudness of the gated blocks
+    fn range(&self) -> f64 {
+        let bins: Vec<usize> = self.gated(RANGE_RELATIVE_GATE).collect();
+        let count: u64 = bins.iter().map(|i| self.counts[*i]).sum();
+        if count == 0 {
+            return 0.0;
+        }
+        let percentile = |p: f64| {
+            let index = ((count - 1) as f64 * p).round() as u64;
+            let mut seen = 0;
+            for i in bins.iter() {
+                seen += self.counts[*i];
+                if seen > index {
+                    return self.loudness[*i];
+                }
+            }
+            self.loudness[*bins.last().unwrap()]
+        };
+        percentile(0.95) - percentile(0.1)
+    }
+}
+
+/// Highest absolute value of the signal when oversampled 4 times
+#[derive(Debug, Clone)]
+struct TruePeak {
+    history: [f64; 12],
+    peak: f64,
+}
+
+impl TruePeak {
+    fn new() -> Self {
+        Self {
+            history: [0.0; 12],
+            peak: 0.0,
+        }
+    }
+
+    fn process(&mut self, x: f32) {
+        self.history.rotate_right(1);
+        self.history[0] = x as f64;
+        for phase in TRUE_PEAK_PHASES.iter() {
+            let y: f64 = phase.iter().zip(&self.history).map(|(c, h)| c * h).sum();
+            self.peak = self.peak.max(y.abs());
+        }
+        self.peak = self.peak.max(x.abs() as f64);
+    }
+
+    fn dbtp(&self) -> f32 {
+        (20.0 * self.peak.log10()) as f32
+    }
+}
+
+/// EBU R128 loudness meter
+pub struct Loudness {
+    channels: usize,
+    filters: Vec<[Biquad; 2]>,
+    true_peaks: Vec<TruePeak>,
+    step_size: usize,
+    step_position: usize,
+    /// Sum of the squared k-weighted samples of every channel in the current step
+    step_energy: Vec<f64>,
+    /// Mean square power of the latest steps summed over all channels
+    steps: VecDeque<f64>,
+    /// Momentary blocks for the integrated loudness
+    blocks: Histogram,
+    /// Short term blocks for the loudness range
+    short_term_blocks: Histogram,
+    interval: usize,
+    since_emit: usize,
+    reset: LoudnessReset,
+}
+
+impl Loudness {
+    pub fn new(rate: f32, spec: &Spec, reset: LoudnessReset) -> Self {
+        let channels = spec.channels as usize;
+        let sample_rate = spec.rate as usize;
+        Self {
+            channels,
+            filters: (0..channels)
+                .map(|_| k_weighting(sample_rate as f64))
+                .collect(),
+            true_peaks: vec![TruePeak::new(); channels],
+            step_size: sample_rate / STEPS_PER_SECOND,
+            step_position: 0,
+            step_energy: vec![0.0; channels],
+            steps: VecDeque::with_capacity(SHORT_TERM_STEPS),
+            blocks: Histogram::new(),
+            short_term_blocks: Histogram::new(),
+            interval: ((sample_rate as f32 / rate) as usize).max(1),
+            since_emit: 0,
+            reset,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.blocks.clear();
+        self.short_term_blocks.clear();
+        self.true_peaks = vec![TruePeak::new(); self.channels];
+    }
+
+    /// Mean power over the latest `n` steps
+    fn window(&self, n: usize) -> Option<f64> {
+        if self.steps.len() < n {
+            return None;
+        }
+        Some(self.steps.iter().rev().take(n).sum::<f64>() / n as f64)
+    }
+
+    fn end_step(&mut self) {
+        let power = self
+            .step_energy
+            .iter()
+            .map(|e| e / self.step_size as f64)
+            .sum();
+        self.step_energy.iter_mut().for_each(|e| *e = 0.0);
+        if self.steps.len() == SHORT_TERM_STEPS {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(power);
+
+        if let Some(p) = self.window(MOMENTARY_STEPS) {
+            self.blocks.add(p);
+        }
+        if let Some(p) = self.window(SHORT_TERM_STEPS) {
+            self.short_term_blocks.add(p);
+        }
+    }
+
+    fn event(&self, latency: &Latency) -> LoudnessEvent {
+        let integrated = self.blocks.integrated();
+        let range = self.short_term_blocks.range();
+
+        let loudness = |n| self.window(n).map(lufs).unwrap_or(f64::NEG_INFINITY) as f32;
+        LoudnessEvent {
+            momentary: loudness(MOMENTARY_STEPS),
+            short_term: loudness(SHORT_TERM_STEPS),
+            integrated: integrated as f32,
+            range: range as f32,
+            true_peak: self.true_peaks.iter().map(TruePeak::dbtp).collect(),
+            latency: latency.clone(),
+        }
+    }
+}
+
+impl Processor for Loudness {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::Raw(raw) = event else {
+            return;
+        };
+
+        if self.reset.take() {
+            self.clear();
+        }
+
+        for frame in raw.data.chunks_exact(self.channels) {
+            for (c, x) in frame.iter().enumerate() {
+                self.true_peaks[c].process(*x);
+                let [shelf, high_pass] = &mut self.filters[c];
+                let y = high_pass.process(shelf.process(*x)) as f64;
+                self.step_energy[c] += y * y;
+            }
+
+            self.step_position += 1;
+            if self.step_position == self.step_size {
+                self.step_position = 0;
+                self.end_step();
+            }
+
+            self.since_emit += 1;
+            if self.since_emit >= self.interval {
+                self.since_emit = 0;
+                emit(Event::Loudness(self.event(&raw.latency)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::feature_flags;
+    use crate::feature::testing::{run, sine, spec};
+
+    fn loudness(samples: &[f32]) -> LoudnessEvent {
+        let loudness = Loudness::new(10.0, &spec(), LoudnessReset::default());
+        let events = run(vec![(feature_flags::RAW, Box::new(loudness))], samples);
+        match events.last() {
+            Some(Event::Loudness(loudness)) => loudness.clone(),
+            event => panic!("{event:?}"),
+        }
+    }
+
+    #[test]
+    fn tone_at_reference_level() {
+        // a 1 kHz sine with a peak of 0.1 is -23 LUFS in a single channel
+        let loudness = loudness(&sine(1000.0, 0.1, 10.0));
+        assert!((loudness.momentary + 23.0).abs() < 0.1, "{loudness:?}");
+        assert!((loudness.short_term + 23.0).abs() < 0.1, "{loudness:?}");
+        assert!((loudness.integrated + 23.0).abs() < 0.1, "{loudness:?}");
+        assert!(loudness.range < 0.2, "{loudness:?}");
+        assert!((loudness.true_peak[0] + 20.0).abs() < 0.1, "{loudness:?}");
+    }
+
+    #[test]
+    fn range_of_two_levels() {
+        let mut samples = sine(1000.0, 0.1, 20.0);
+        samples.extend(sine(1000.0, 0.1 / 10f32.powf(0.5), 20.0));
+        let loudness = loudness(&samples);
+        assert!((loudness.momentary + 33.0).abs() < 0.1, "{loudness:?}");
+        assert!((loudness.range - 10.0).abs() < 0.5, "{loudness:?}");
+        // both halves are above the relative gate so their powers are averaged
+        let integrated = -23.0 + 10.0 * (1.1f32 / 2.0).log10();
+        assert!(
+            (loudness.integrated - integrated).abs() < 0.1,
+            "{loudness:?}"
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use crate::dsp::amplitude_to_db;
+use crate::latency::Latency;
+use crate::Event;
+
+use super::constant_q::ConstantQEvent;
+use super::Processor;
+
+/// Amount of harmonics summed for the salience of a note
+const HARMONICS: usize = 5;
+/// Weight of every next harmonic
+const HARMONIC_DECAY: f32 = 0.8;
+/// Notes weaker than the strongest note by this many dB are ignored
+const RELATIVE_THRESHOLD: f32 = -30.0;
+/// A note that is a harmonic of a stronger note needs at least this part of its salience, a note
+/// below a stronger note that is one of its harmonics this part of its magnitude
+const HARMONIC_RATIO: f32 = 0.5;
+/// Intervals in semitones of the second to sixth harmonic
+const HARMONIC_INTERVALS: [i32; 5] = [12, 19, 24, 28, 31];
+/// Consecutive frames a note has to be found before it starts
+const ONSET_FRAMES: usize = 2;
+/// Consecutive frames a note has to be missing before it stops
+const OFFSET_FRAMES: usize = 3;
+/// Level in dBFS mapped to velocity 1, 0 dBFS is velocity 127
+const VELOCITY_FLOOR: f32 = -60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Note {
+    /// Midi note number
+    pub note: u8,
+    /// Midi velocity estimated from the level at the onset
+    pub velocity: u8,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NotesEvent {
+    /// Every note currently sounding
+    pub sounding: Vec<Note>,
+    /// Notes that started this frame
+    pub onsets: Vec<Note>,
+    /// Notes that stopped this frame
+    pub offsets: Vec<Note>,
+    pub latency: Latency,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Track {
+    /// Consecutive frames the note was found
+    found: usize,
+    /// Consecutive frames the note was missing
+    missing: usize,
+    sounding: Option<Note>,
+}
+
+/// Multi pitch estimation by harmonic summation on the constant q bins
+pub struct Notes {
+    /// Level in dBFS a note needs to be detected
+    threshold: f32,
+    max_polyphony: usize,
+    tracks: Vec<Track>,
+}
+
+impl Notes {
+    pub fn new(threshold: f32, max_polyphony: usize) -> Self {
+        Self {
+            threshold,
+            max_polyphony,
+            tracks: vec![Track::default(); 128],
+        }
+    }
+
+    /// Magnitude of the constant q bin closest to `frequency`
+    fn magnitude(constant_q: &ConstantQEvent, frequency: f32) -> f32 {
+        let k = (constant_q.bins_per_octave as f32 * (frequency / constant_q.min_frequency).log2())
+            .round();
+        if k < 0.0 {
+            return 0.0;
+        }
+        constant_q
+            .magnitudes
+            .get(k as usize)
+            .cloned()
+            .unwrap_or(0.0)
+    }
+
+    /// Notes found in a single frame with their level in dBFS
+    fn detect(&self, constant_q: &ConstantQEvent) -> Vec<(u8, f32)> {
+        let frequency = |n: usize| 440.0 * 2f32.powf((n as f32 - 69.0) / 12.0);
+        let salience: Vec<f32> = (0..128)
+            .map(|n| {
+                let f0 = frequency(n);
+                if f0 < constant_q.min_frequency {
+                    return 0.0;
+                }
+                (1..=HARMONICS)
+                    .map(|h| {
+                        HARMONIC_DECAY.powi(h as i32 - 1)
+                            * Self::magnitude(constant_q, f0 * h as f32)
+                    })
+                    .sum()
+            })
+            .collect();
+        let max = salience.iter().cloned().fold(0.0, f32::max);
+        if max <= 0.0 {
+            return vec![];
+        }
+
+        let mut candidates: Vec<usize> = (1..127)
+            .filter(|n| {
+                salience[*n] > salience[n - 1]
+                    && salience[*n] >= salience[n + 1]
+                    && amplitude_to_db(salience[*n] / max) > RELATIVE_THRESHOLD
+                    && amplitude_to_db(Self::magnitude(constant_q, frequency(*n))) > self.threshold
+            })
+            .collect();
+        candidates.sort_by(|a, b| salience[*b].total_cmp(&salience[*a]));
+
+        let mut notes: Vec<usize> = vec![];
+        for n in candidates {
+            if notes.len() == self.max_polyphony {
+                break;
+            }
+            // harmonics of a stronger note are only notes themselves when they are strong enough,
+            // and so are notes below it that only borrow its salience, eg. from a smeared onset
+            let fundamental = |n: usize| Self::magnitude(constant_q, frequency(n));
+            let harmonic = notes.iter().any(|f| {
+                let interval = n as i32 - *f as i32;
+                (HARMONIC_INTERVALS.contains(&interval)
+                    && salience[n] < HARMONIC_RATIO * salience[*f])
+                    || (HARMONIC_INTERVALS.contains(&-interval)
+                        && fundamental(n) < HARMONIC_RATIO * fundamental(*f))
+            });
+            if !harmonic {
+                notes.push(n);
+            }
+        }
+
+        notes
+            .into_iter()
+            .map(|n| {
+                let level = amplitude_to_db(Self::magnitude(constant_q, frequency(n)));
+                (n as u8, level)
+            })
+            .collect()
+    }
+}
+
+impl Processor for Notes {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::ConstantQ(constant_q) = event else {
+            return;
+        };
+
+        let detected = self.detect(constant_q);
+        let mut onsets = vec![];
+        let mut offsets = vec![];
+        for (n, track) in self.tracks.iter_mut().enumerate() {
+            match detected.iter().find(|(note, _)| *note as usize == n) {
+                Some((note, level)) => {
+                    track.found += 1;
+                    track.missing = 0;
+                    if track.sounding.is_none() && track.found >= ONSET_FRAMES {
+                        let velocity = 1.0 + 126.0 * (1.0 - level / VELOCITY_FLOOR).clamp(0.0, 1.0);
+                        let note = Note {
+                            note: *note,
+                            velocity: velocity as u8,
+                        };
+                        track.sounding = Some(note);
+                        onsets.push(note);
+                    }
+                }
+                None => {
+                    track.found = 0;
+                    track.missing += 1;
+                    if track.missing >= OFFSET_FRAMES {
+                        if let Some(note) = track.sounding.take() {
+                            offsets.push(note);
+                        }
+                    }
+                }
+            }
+        }
+
+        emit(Event::Notes(NotesEvent {
+            sounding: self.tracks.iter().filter_map(|t| t.sounding).collect(),
+            onsets,
+            offsets,
+            latency: constant_q.latency.clone(),
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::constant_q::ConstantQ;
+    use crate::feature::feature_flags;
+    use crate::feature::spectrum::Spectrum;
+    use crate::feature::testing::{run, sine, spec, RATE};
+    use std::time::UNIX_EPOCH;
+
+    fn run_notes(tone: Vec<f32>) -> Vec<NotesEvent> {
+        let mut samples = vec![0.0; RATE as usize / 2];
+        samples.extend(tone);
+        samples.extend(vec![0.0; RATE as usize]);
+        run(
+            vec![
+                (
+                    feature_flags::RAW,
+                    Box::new(Spectrum::new(4096, 1024, &spec())),
+                ),
+                (
+                    feature_flags::SPECTRUM,
+                    Box::new(ConstantQ::new(36, 55.0, 7, RATE as f32 / 4096.0, 2049)),
+                ),
+                (feature_flags::CONSTANT_Q, Box::new(Notes::new(-50.0, 4))),
+            ],
+            &samples,
+        )
+        .into_iter()
+        .filter_map(|e| match e {
+            Event::Notes(notes) => Some(notes),
+            _ => None,
+        })
+        .collect()
+    }
+
+    fn time(notes: &NotesEvent) -> f32 {
+        let time = notes.latency.captured.duration_since(UNIX_EPOCH);
+        time.unwrap().as_secs_f32()
+    }
+
+    fn numbers(notes: &[Note]) -> Vec<u8> {
+        let mut numbers: Vec<u8> = notes.iter().map(|n| n.note).collect();
+        numbers.sort();
+        numbers
+    }
+
+    #[test]
+    fn a4_of_sine() {
+        let changes: Vec<_> = run_notes(sine(440.0, 0.5, 1.0))
+            .into_iter()
+            .filter(|n| !n.onsets.is_empty() || !n.offsets.is_empty())
+            .collect();
+        assert_eq!(changes.len(), 2, "{changes:?}");
+        assert_eq!(numbers(&changes[0].onsets), [69]);
+        assert!((0.5..0.7).contains(&time(&changes[0])), "{changes:?}");
+        assert_eq!(numbers(&changes[1].offsets), [69]);
+        assert!((1.5..1.8).contains(&time(&changes[1])), "{changes:?}");
+    }
+
+    #[test]
+    fn major_third() {
+        let samples: Vec<f32> = sine(440.0, 0.3, 1.0)
+            .iter()
+            .zip(sine(554.37, 0.3, 1.0))
+            .map(|(a, b)| a + b)
+            .collect();
+        let notes = run_notes(samples);
+        // the edges of the tone smear over the low bins, only check the steady part
+        for n in notes.iter().filter(|n| (0.8..1.4).contains(&time(n))) {
+            assert_eq!(numbers(&n.sounding), [69, 73], "{n:?}");
+        }
+        assert!(notes.last().unwrap().sounding.is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+use pulse::sample::Spec;
+
+use crate::dsp::{mean_square, Framer};
+use crate::latency::Latency;
+use crate::Event;
+
+use super::Processor;
+
+/// Amount of samples between two values of the onset envelope
+pub const ONSET_HOP: usize = 512;
+const ONSET_FRAME: usize = 1024;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OnsetEvent {
+    /// Onset strength of every hop of `ONSET_HOP` samples completed by the raw audio
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub envelope: Vec<f32>,
+    pub latency: Latency,
+}
+
+/// Half-wave rectified difference of log energy, rises on every onset
+#[derive(Debug, Clone)]
+pub struct OnsetEnvelope {
+    framer: Framer,
+    previous: f32,
+}
+
+impl Default for OnsetEnvelope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OnsetEnvelope {
+    pub fn new() -> Self {
+        Self {
+            framer: Framer::new(ONSET_FRAME, ONSET_HOP),
+            previous: 0.0,
+        }
+    }
+
+    /// Amount of envelope values per second
+    pub fn rate(spec: &Spec) -> f32 {
+        spec.rate as f32 / ONSET_HOP as f32
+    }
+
+    pub fn push<F>(&mut self, data: &[f32], mut f: F)
+    where
+        F: FnMut(f32),
+    {
+        let previous = &mut self.previous;
+        self.framer.push(data, |frame| {
+            let energy = (1.0 + 1000.0 * mean_square(frame)).ln();
+            f((energy - *previous).max(0.0));
+            *previous = energy;
+        });
+    }
+}
+
+/// Onset envelope of the raw audio, shared by the features following the rhythm
+#[derive(Debug, Clone, Default)]
+pub struct Onset {
+    envelope: OnsetEnvelope,
+}
+
+impl Processor for Onset {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::Raw(raw) = event else {
+            return;
+        };
+
+        let mut envelope = vec![];
+        self.envelope.push(&raw.data, |v| envelope.push(v));
+        if envelope.is_empty() {
+            return;
+        }
+        emit(Event::Onset(OnsetEvent {
+            envelope,
+            latency: raw.latency.clone(),
+        }));
+    }
+}
@@ -0,0 +1,161 @@
+use pulse::sample::Spec;
+
+use crate::dsp::{frequency_to_midi, mean_square, parabolic_offset, Framer};
+use crate::latency::Latency;
+use crate::Event;
+
+use super::Processor;
+
+/// Time between two pitch estimates in seconds
+const HOP: f32 = 0.01;
+/// Frames quieter than this mean square are never voiced
+const SILENCE: f32 = 1e-6;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PitchEvent {
+    /// Fundamental frequency in Hz
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub frequency: f32,
+    /// Nearest midi note number
+    pub note: u8,
+    /// Deviation from `note` in cents between -50 and 50
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub cents: f32,
+    /// Whether a pitch was found, when false `frequency` is the best guess
+    pub voiced: bool,
+    /// How clear the pitch is between 0 and 1
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub confidence: f32,
+    pub latency: Latency,
+}
+
+/// Yin fundamental frequency estimator (de Cheveigné & Kawahara, 2002)
+struct Yin {
+    min_lag: usize,
+    max_lag: usize,
+    threshold: f32,
+    difference: Vec<f32>,
+}
+
+impl Yin {
+    /// Amount of samples needed for a single estimate
+    fn frame_size(&self) -> usize {
+        self.max_lag * 2 + 2
+    }
+
+    /// Returns the period in samples and how aperiodic the frame is at that period
+    fn estimate(&mut self, frame: &[f32]) -> (f32, f32) {
+        let window = self.max_lag;
+        let d = &mut self.difference;
+
+        // cumulative mean normalized difference function
+        d[0] = 1.0;
+        let mut sum = 0.0;
+        for lag in 1..d.len() {
+            let difference: f32 = frame[..window]
+                .iter()
+                .zip(&frame[lag..lag + window])
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum();
+            sum += difference;
+            d[lag] = if sum > 0.0 {
+                difference * lag as f32 / sum
+            } else {
+                1.0
+            };
+        }
+
+        // first dip below the threshold, otherwise the global minimum
+        let mut lag = (self.min_lag..=self.max_lag)
+            .find(|lag| d[*lag] < self.threshold)
+            .unwrap_or_else(|| {
+                (self.min_lag..=self.max_lag)
+                    .min_by(|a, b| d[*a].total_cmp(&d[*b]))
+                    .unwrap_or(self.max_lag)
+            });
+        while lag < self.max_lag && d[lag + 1] < d[lag] {
+            lag += 1;
+        }
+
+        let offset = parabolic_offset(d[lag - 1], d[lag], d[lag + 1]);
+        (lag as f32 + offset, d[lag])
+    }
+}
+
+pub struct Pitch {
+    framer: Framer,
+    yin: Yin,
+    rate: f32,
+}
+
+impl Pitch {
+    pub fn new(min_frequency: f32, max_frequency: f32, threshold: f32, spec: &Spec) -> Self {
+        let rate = spec.rate as f32;
+        let max_lag = (rate / min_frequency).ceil() as usize;
+        let yin = Yin {
+            min_lag: ((rate / max_frequency).floor() as usize).max(2),
+            max_lag,
+            threshold,
+            difference: vec![0.0; max_lag + 2],
+        };
+        let size = yin.frame_size();
+        let hop = ((HOP * rate) as usize).clamp(1, size);
+        Self {
+            framer: Framer::new(size, hop),
+            yin,
+            rate,
+        }
+    }
+}
+
+impl Processor for Pitch {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::Raw(raw) = event else {
+            return;
+        };
+
+        let (yin, rate) = (&mut self.yin, self.rate);
+        self.framer.push(&raw.data, |frame| {
+            let (period, aperiodicity) = yin.estimate(frame);
+            let frequency = rate / period;
+            let midi = frequency_to_midi(frequency);
+            let note = midi.round().clamp(0.0, 127.0);
+            emit(Event::Pitch(PitchEvent {
+                frequency,
+                note: note as u8,
+                cents: (midi - note) * 100.0,
+                voiced: aperiodicity < yin.threshold && mean_square(frame) > SILENCE,
+                confidence: (1.0 - aperiodicity).clamp(0.0, 1.0),
+                latency: raw.latency.clone(),
+            }));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::feature_flags;
+    use crate::feature::testing::{run, sine, spec};
+
+    #[test]
+    fn a4_of_sine() {
+        let pitch = Pitch::new(60.0, 1000.0, 0.15, &spec());
+        let events = run(
+            vec![(feature_flags::RAW, Box::new(pitch))],
+            &sine(440.0, 0.5, 1.0),
+        );
+        assert!(events.len() > 50, "{}", events.len());
+        // skip the estimates of frames that are not filled with the sine yet
+        for event in events.iter().skip(10) {
+            let Event::Pitch(pitch) = event else {
+                panic!("{event:?}");
+            };
+            assert!(pitch.voiced, "{pitch:?}");
+            assert_eq!(pitch.note, 69, "{pitch:?}");
+            assert!(pitch.cents.abs() < 5.0, "{pitch:?}");
+            assert!((pitch.frequency - 440.0).abs() < 1.0, "{pitch:?}");
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use pulse::sample::Spec;
+
+use crate::dsp::{mean_square, Framer};
+use crate::latency::Latency;
+use crate::Event;
+
+use super::Processor;
+
+/// Length of the blocks the level is measured over in seconds
+const BLOCK: f32 = 0.01;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SilenceEvent {
+    /// True when silence started, false when the signal came back
+    pub silent: bool,
+    /// Level of the latest block in dBFS
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub level: f32,
+    /// How long the previous state lasted
+    pub previous: Duration,
+    pub latency: Latency,
+}
+
+pub struct Silence {
+    framer: Framer,
+    block: Duration,
+    /// Level in dBFS below which the signal is silent
+    threshold: f32,
+    /// Level in dBFS above which the signal is present again
+    release: f32,
+    /// Amount of blocks a change has to last before it is reported
+    hold: usize,
+    silent: bool,
+    /// Consecutive blocks contradicting the current state
+    pending: usize,
+    /// Blocks since the last state change
+    since_change: usize,
+}
+
+impl Silence {
+    /// `threshold` and `hysteresis` are in dB, `hold` is in milliseconds
+    pub fn new(threshold: f32, hysteresis: f32, hold: f32, spec: &Spec) -> Self {
+        let channels = spec.channels as usize;
+        // very low rates still need at least a frame per block
+        let frames = ((BLOCK * spec.rate as f32) as usize).max(1);
+        let block = Duration::from_secs_f32(frames as f32 / spec.rate as f32);
+        Self {
+            framer: Framer::new(frames * channels, frames * channels),
+            block,
+            threshold,
+            release: threshold + hysteresis,
+            hold: ((hold / 1000.0 / block.as_secs_f32()).ceil() as usize).max(1),
+            silent: false,
+            pending: 0,
+            since_change: 0,
+        }
+    }
+}
+
+impl Processor for Silence {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::Raw(raw) = event else {
+            return;
+        };
+
+        let mut levels = vec![];
+        self.framer.push(&raw.data, |block| {
+            levels.push(10.0 * mean_square(block).log10())
+        });
+        for level in levels {
+            self.since_change += 1;
+            let contradicts = if self.silent {
+                level > self.release
+            } else {
+                level < self.threshold
+            };
+            if !contradicts {
+                self.pending = 0;
+                continue;
+            }
+
+            self.pending += 1;
+            if self.pending < self.hold {
+                continue;
+            }
+
+            // the change started when the first contradicting block came in
+            let previous = self.since_change - self.pending;
+            self.silent = !self.silent;
+            self.since_change = self.pending;
+            self.pending = 0;
+            emit(Event::Silence(SilenceEvent {
+                silent: self.silent,
+                level,
+                previous: self.block * previous as u32,
+                latency: raw.latency.clone(),
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::feature_flags;
+    use crate::feature::testing::{run, sine, spec};
+    use crate::latency::Instant;
+    use crate::RawEvent;
+    use std::borrow::Cow;
+    use std::time::SystemTime;
+
+    #[test]
+    fn start_and_end_around_tone() {
+        let mut samples = vec![0.0; 48000];
+        samples.extend(sine(1000.0, 0.5, 2.0));
+        samples.extend(vec![0.0; 96000]);
+        let silence = Silence::new(-60.0, 6.0, 100.0, &spec());
+        let events: Vec<_> = run(vec![(feature_flags::RAW, Box::new(silence))], &samples)
+            .into_iter()
+            .filter_map(|e| match e {
+                Event::Silence(silence) => Some(silence),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(events.len(), 3, "{events:?}");
+        assert!(events[0].silent && events[0].previous.is_zero());
+        // the tone ends the first second of silence and lasts two seconds
+        assert!(!events[1].silent);
+        assert_eq!(events[1].previous, Duration::from_secs(1));
+        assert!((events[1].level + 9.03).abs() < 0.1, "{:?}", events[1]);
+        assert!(events[2].silent);
+        assert_eq!(events[2].previous, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn low_rate() {
+        let spec = Spec { rate: 50, ..spec() };
+        let mut silence = Silence::new(-60.0, 6.0, 100.0, &spec);
+        let mut events = vec![];
+        let raw = Event::Raw(RawEvent {
+            data: Cow::Borrowed(&[0.0; 10]),
+            latency: Latency {
+                internal: Instant::None,
+                airap: Instant::None,
+                captured: SystemTime::UNIX_EPOCH,
+            },
+        });
+        silence.process(&raw, &mut |e| events.push(e));
+        assert_eq!(events.len(), 1);
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use pulse::sample::Spec;
+
+use crate::dsp::{Framer, SpectrumAnalyzer};
+use crate::latency::Latency;
+use crate::Event;
+
+use super::Processor;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpectrumEvent {
+    /// Magnitude of every frequency bin from 0Hz up to the nyquist frequency, a full scale sine has a peak of 1
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub magnitudes: Arc<[f32]>,
+    /// Width of a single bin in Hz
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub resolution: f32,
+    pub latency: Latency,
+}
+
+impl SpectrumEvent {
+    /// Center frequency of bin `i` in Hz
+    pub fn frequency(&self, i: usize) -> f32 {
+        i as f32 * self.resolution
+    }
+}
+
+/// Shared fft stage so features working on the spectrum don't each compute their own
+pub struct Spectrum {
+    framer: Framer,
+    analyzer: SpectrumAnalyzer,
+    resolution: f32,
+}
+
+impl Spectrum {
+    pub fn new(size: usize, hop: usize, spec: &Spec) -> Self {
+        Self {
+            framer: Framer::new(size, hop),
+            analyzer: SpectrumAnalyzer::new(size),
+            resolution: spec.rate as f32 / size as f32,
+        }
+    }
+}
+
+impl Processor for Spectrum {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::Raw(raw) = event else {
+            return;
+        };
+
+        let (analyzer, resolution) = (&mut self.analyzer, self.resolution);
+        self.framer.push(&raw.data, |frame| {
+            emit(Event::Spectrum(SpectrumEvent {
+                magnitudes: analyzer.magnitudes(frame).into(),
+                resolution,
+                latency: raw.latency.clone(),
+            }));
+        });
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+
+use pulse::sample::Spec;
+
+use crate::dsp::parabolic_offset;
+use crate::latency::Latency;
+use crate::Event;
+
+use super::onset::OnsetEnvelope;
+use super::Processor;
+
+/// Seconds of onset envelope used for estimating the tempo
+const WINDOW: f32 = 6.0;
+/// How often the tempo gets estimated in seconds
+const ESTIMATE_INTERVAL: f32 = 0.5;
+/// Relative difference in bpm that is still seen as the same tempo
+const TOLERANCE: f32 = 0.04;
+/// How fast the current tempo follows small changes
+const SMOOTHING: f32 = 0.3;
+/// Amount of consecutive estimates needed before switching to a different tempo
+const CHANGE_CONFIRMATIONS: usize = 3;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TempoEvent {
+    /// Estimated beats per minute
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub bpm: f32,
+    /// How periodic the signal is at `bpm` between 0 and 1
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub confidence: f32,
+    pub latency: Latency,
+}
+
+pub struct Tempo {
+    envelope: VecDeque<f32>,
+    capacity: usize,
+    envelope_rate: f32,
+    min_lag: usize,
+    max_lag: usize,
+    estimate_interval: usize,
+    since_estimate: usize,
+    bpm: Option<f32>,
+    candidate: Option<(f32, usize)>,
+}
+
+impl Tempo {
+    pub fn new(min_bpm: f32, max_bpm: f32, spec: &Spec) -> Self {
+        let envelope_rate = OnsetEnvelope::rate(spec);
+        let max_lag = (60.0 * envelope_rate / min_bpm).ceil() as usize;
+        // always keep enough history to see two periods of the slowest tempo
+        let capacity = ((WINDOW * envelope_rate) as usize).max(max_lag * 2 + 1);
+        Self {
+            envelope: VecDeque::with_capacity(capacity),
+            capacity,
+            envelope_rate,
+            min_lag: ((60.0 * envelope_rate / max_bpm).floor() as usize).max(1),
+            max_lag,
+            estimate_interval: (ESTIMATE_INTERVAL * envelope_rate) as usize,
+            since_estimate: 0,
+            bpm: None,
+            candidate: None,
+        }
+    }
+
+    /// Find the most periodic lag in the envelope, returns the bpm and confidence
+    fn estimate(&self) -> Option<(f32, f32)> {
+        let n = self.envelope.len();
+        if n < self.max_lag * 2 {
+            return None;
+        }
+        let mean = self.envelope.iter().sum::<f32>() / n as f32;
+        let x: Vec<f32> = self.envelope.iter().map(|v| v - mean).collect();
+        let autocorrelation = |lag: usize| -> f32 {
+            if lag >= n {
+                return 0.0;
+            }
+            x[..n - lag]
+                .iter()
+                .zip(&x[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / (n - lag) as f32
+        };
+
+        let energy = autocorrelation(0);
+        if energy <= f32::EPSILON {
+            return None;
+        }
+
+        // also reward a peak at twice the lag so the true period wins over its harmonics
+        let score = |lag: usize| autocorrelation(lag) + 0.5 * autocorrelation(lag * 2);
+        let scores: Vec<f32> = (self.min_lag - 1..=self.max_lag + 1).map(score).collect();
+        let (best, _) = scores[1..scores.len() - 1]
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        let offset = parabolic_offset(scores[best], scores[best + 1], scores[best + 2]);
+        let lag = (self.min_lag + best) as f32 + offset;
+
+        let confidence = (autocorrelation(self.min_lag + best) / energy).clamp(0.0, 1.0);
+        Some((60.0 * self.envelope_rate / lag, confidence))
+    }
+
+    /// Follow small tempo drift directly but only switch to a different tempo once it is stable
+    fn update(&mut self, bpm: f32) -> f32 {
+        let same = |a: f32, b: f32| (a - b).abs() / b < TOLERANCE;
+        match self.bpm {
+            Some(current) if same(bpm, current) => {
+                self.candidate = None;
+                self.bpm = Some(current + SMOOTHING * (bpm - current));
+            }
+            Some(_) => {
+                let count = match self.candidate {
+                    Some((candidate, count)) if same(bpm, candidate) => count + 1,
+                    _ => 1,
+                };
+                if count >= CHANGE_CONFIRMATIONS {
+                    self.candidate = None;
+                    self.bpm = Some(bpm);
+                } else {
+                    self.candidate = Some((bpm, count));
+                }
+            }
+            None => self.bpm = Some(bpm),
+        }
+        self.bpm.unwrap_or(bpm)
+    }
+}
+
+impl Processor for Tempo {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::Onset(onset) = event else {
+            return;
+        };
+
+        for v in onset.envelope.iter() {
+            if self.envelope.len() == self.capacity {
+                self.envelope.pop_front();
+            }
+            self.envelope.push_back(*v);
+        }
+
+        self.since_estimate += onset.envelope.len();
+        if self.since_estimate < self.estimate_interval {
+            return;
+        }
+        self.since_estimate = 0;
+
+        if let Some((bpm, confidence)) = self.estimate() {
+            let bpm = self.update(bpm);
+            emit(Event::Tempo(TempoEvent {
+                bpm,
+                confidence,
+                latency: onset.latency.clone(),
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::feature_flags;
+    use crate::feature::onset::Onset;
+    use crate::feature::testing::{clicks, run, spec};
+
+    #[test]
+    fn tempo_of_clicks() {
+        let tempo = Tempo::new(60.0, 180.0, &spec());
+        let events = run(
+            vec![
+                (feature_flags::RAW, Box::new(Onset::default())),
+                (feature_flags::ONSET, Box::new(tempo)),
+            ],
+            &clicks(120.0, 10.0),
+        );
+        let Some(tempo) = events.iter().rev().find_map(|e| match e {
+            Event::Tempo(tempo) => Some(tempo),
+            _ => None,
+        }) else {
+            panic!("no tempo");
+        };
+        assert!((tempo.bpm - 120.0).abs() < 1.0, "{}", tempo.bpm);
+        assert!(tempo.confidence > 0.5, "{}", tempo.confidence);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::latency::Latency;
+use crate::Event;
+
+use super::Processor;
+
+/// Frequency range in Hz containing most of the energy of speech
+const SPEECH_BAND: (f32, f32) = (300.0, 3400.0);
+/// Minimal part of the energy that has to be in the speech band
+const MIN_BAND_RATIO: f32 = 0.4;
+/// Spectra flatter than this are treated as noise
+const MAX_FLATNESS: f32 = 0.5;
+/// Frames quieter than this in dB are never speech
+const MIN_LEVEL: f32 = -70.0;
+/// How fast the noise floor rises in dB per second
+const NOISE_RISE: f32 = 3.0;
+/// Consecutive speech frames needed before speech starts
+const ONSET_FRAMES: usize = 3;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VoiceActivityEvent {
+    /// Whether the frame is speech after smoothing and hangover
+    pub speech: bool,
+    /// True on the frame where speech started or ended
+    pub changed: bool,
+    /// How speech like the frame is between 0 and 1 before smoothing
+    #[cfg_attr(feature = "serde", serde(with = "crate::float"))]
+    pub score: f32,
+    pub latency: Latency,
+}
+
+/// Energy and spectral shape based voice activity detector
+pub struct VoiceActivity {
+    /// Amount of dB the speech band has to be above the noise floor
+    threshold: f32,
+    /// Frames speech continues after the last speech frame
+    hangover: usize,
+    /// Noise floor rise per frame in dB
+    noise_rise: f32,
+    noise: Option<f32>,
+    speech: bool,
+    onset: usize,
+    hangover_left: usize,
+}
+
+impl VoiceActivity {
+    /// `hangover` is in milliseconds and `hop` is the time between spectra in seconds
+    pub fn new(threshold: f32, hangover: f32, hop: f32) -> Self {
+        Self {
+            threshold,
+            hangover: (hangover / 1000.0 / hop).ceil() as usize,
+            noise_rise: NOISE_RISE * hop,
+            noise: None,
+            speech: false,
+            onset: 0,
+            hangover_left: 0,
+        }
+    }
+}
+
+impl Processor for VoiceActivity {
+    fn process(&mut self, event: &Event, emit: &mut dyn FnMut(Event<'static>)) {
+        let Event::Spectrum(spectrum) = event else {
+            return;
+        };
+
+        let power: Vec<f32> = spectrum.magnitudes.iter().map(|m| m * m).collect();
+        let total: f32 = power[1..].iter().sum();
+        let band: f32 = power
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (SPEECH_BAND.0..SPEECH_BAND.1).contains(&spectrum.frequency(*i)))
+            .map(|(_, p)| p)
+            .sum();
+        let level = 10.0 * (band + f32::EPSILON).log10();
+        let ratio = if total > 0.0 { band / total } else { 0.0 };
+
+        let n = (power.len() - 1) as f32;
+        let geometric = (power[1..]
+            .iter()
+            .map(|p| (p + f32::EPSILON).ln())
+            .sum::<f32>()
+            / n)
+            .exp();
+        let flatness = geometric / (total / n + f32::EPSILON);
+
+        // follow drops in level directly but rise slowly so speech doesn't become the noise floor
+        let noise = match self.noise {
+            Some(noise) if level > noise => (noise + self.noise_rise).min(level),
+            _ => level,
+        };
+        self.noise = Some(noise);
+
+        let snr = level - noise;
+        let score = 1.0 / (1.0 + (-(snr - self.threshold) / 3.0).exp());
+        let frame_speech = snr > self.threshold
+            && level > MIN_LEVEL
+            && ratio > MIN_BAND_RATIO
+            && flatness < MAX_FLATNESS;
+
+        let previous = self.speech;
+        if frame_speech {
+            self.onset += 1;
+            if self.onset >= ONSET_FRAMES {
+                self.speech = true;
+                self.hangover_left = self.hangover;
+            }
+        } else {
+            self.onset = 0;
+            if self.hangover_left > 0 {
+                self.hangover_left -= 1;
+            } else {
+                self.speech = false;
+            }
+        }
+
+        emit(Event::VoiceActivity(VoiceActivityEvent {
+            speech: self.speech,
+            changed: self.speech != previous,
+            score: score * ratio.min(1.0),
+            latency: spectrum.latency.clone(),
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::feature_flags;
+    use crate::feature::spectrum::Spectrum;
+    use crate::feature::testing::{run, sine, spec, RATE};
+    use std::time::UNIX_EPOCH;
+
+    /// Times in seconds where speech started or ended
+    fn changes(samples: &[f32]) -> Vec<(bool, f32)> {
+        let hop = 1024.0 / RATE as f32;
+        run(
+            vec![
+                (
+                    feature_flags::RAW,
+                    Box::new(Spectrum::new(2048, 1024, &spec())),
+                ),
+                (
+                    feature_flags::SPECTRUM,
+                    Box::new(VoiceActivity::new(10.0, 200.0, hop)),
+                ),
+            ],
+            samples,
+        )
+        .iter()
+        .filter_map(|e| match e {
+            Event::VoiceActivity(voice) if voice.changed => Some(voice),
+            _ => None,
+        })
+        .map(|voice| {
+            let time = voice.latency.captured.duration_since(UNIX_EPOCH);
+            (voice.speech, time.unwrap().as_secs_f32())
+        })
+        .collect()
+    }
+
+    fn quiet_around(tone: Vec<f32>) -> Vec<f32> {
+        let mut samples = vec![0.0; RATE as usize];
+        samples.extend(tone);
+        samples.extend(vec![0.0; RATE as usize]);
+        samples
+    }
+
+    #[test]
+    fn tone_in_speech_band() {
+        let changes = changes(&quiet_around(sine(1000.0, 0.3, 1.0)));
+        assert_eq!(changes.len(), 2, "{changes:?}");
+        let (started, start) = changes[0];
+        assert!(started && (1.0..1.1).contains(&start), "{changes:?}");
+        // speech continues for the hangover after the tone
+        let (started, end) = changes[1];
+        assert!(!started && (2.2..2.35).contains(&end), "{changes:?}");
+    }
+
+    #[test]
+    fn tone_above_speech_band() {
+        assert!(changes(&quiet_around(sine(6000.0, 0.3, 1.0))).is_empty());
+    }
+}
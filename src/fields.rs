@@ -0,0 +1,126 @@
+use std::time::SystemTime;
+
+use crate::feature::chroma::Mode;
+use crate::feature::notes::Note;
+use crate::Event;
+
+/// Value of a single event field, shared by the encoders of the network outputs
+#[derive(Debug, Clone)]
+pub(crate) enum Value<'a> {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+    Text(&'static str),
+    /// Wall clock time
+    Time(SystemTime),
+    Floats(&'a [f32]),
+    Ints(Vec<i64>),
+    Notes(&'a [Note]),
+    /// Audio samples
+    Samples(&'a [f32]),
+}
+
+/// Value of the field with this name
+pub(crate) fn field_value<'a>(event: &'a Event, name: &str) -> Option<Value<'a>> {
+    fields(event)
+        .into_iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, value)| value)
+}
+
+/// Name and value of every field of the event, the order is stable for every variant
+pub(crate) fn fields<'a>(event: &'a Event) -> Vec<(&'static str, Value<'a>)> {
+    use Value::{Bool, Float, Floats, Int, Ints, Notes, Samples, Text, Time};
+
+    match event {
+        Event::Raw(e) => vec![("samples", Samples(&e.data))],
+        Event::DefaultDeviceChange => vec![],
+        Event::MovingAverage(e) => vec![("average", Floats(&e.average))],
+        Event::Tempo(e) => vec![("bpm", Float(e.bpm)), ("confidence", Float(e.confidence))],
+        Event::Onset(e) => vec![("envelope", Floats(&e.envelope))],
+        Event::Beat(e) => vec![
+            ("index", Int(e.index as i64)),
+            ("bar_position", Int(e.bar_position as i64)),
+            ("bpm", Float(e.bpm)),
+            ("next", Time(e.next)),
+        ],
+        Event::Pitch(e) => vec![
+            ("frequency", Float(e.frequency)),
+            ("note", Int(e.note as i64)),
+            ("cents", Float(e.cents)),
+            ("voiced", Bool(e.voiced)),
+            ("confidence", Float(e.confidence)),
+        ],
+        Event::Spectrum(e) => vec![
+            ("magnitudes", Floats(&e.magnitudes)),
+            ("resolution", Float(e.resolution)),
+        ],
+        Event::Chroma(e) => vec![("chroma", Floats(&e.chroma))],
+        Event::Key(e) => vec![
+            ("tonic", Int(e.tonic as i64)),
+            (
+                "mode",
+                Text(match e.mode {
+                    Mode::Major => "major",
+                    Mode::Minor => "minor",
+                }),
+            ),
+            ("confidence", Float(e.confidence)),
+        ],
+        Event::Loudness(e) => vec![
+            ("momentary", Float(e.momentary)),
+            ("short_term", Float(e.short_term)),
+            ("integrated", Float(e.integrated)),
+            ("range", Float(e.range)),
+            ("true_peak", Floats(&e.true_peak)),
+        ],
+        Event::Level(e) => vec![
+            ("rms", Floats(&e.rms)),
+            ("peak", Floats(&e.peak)),
+            ("peak_hold", Floats(&e.peak_hold)),
+            ("crest_factor", Floats(&e.crest_factor)),
+            ("clips", Ints(e.clips.iter().map(|c| *c as i64).collect())),
+        ],
+        Event::Silence(e) => vec![
+            ("silent", Bool(e.silent)),
+            ("level", Float(e.level)),
+            ("previous", Float(e.previous.as_secs_f32())),
+        ],
+        Event::SpectralDescriptors(e) => vec![
+            ("centroid", Float(e.centroid)),
+            ("spread", Float(e.spread)),
+            ("rolloff", Float(e.rolloff)),
+            ("flatness", Float(e.flatness)),
+            ("flux", Float(e.flux)),
+            ("zero_crossing_rate", Float(e.zero_crossing_rate)),
+            ("contrast", Floats(&e.contrast)),
+        ],
+        Event::VoiceActivity(e) => vec![
+            ("speech", Bool(e.speech)),
+            ("changed", Bool(e.changed)),
+            ("score", Float(e.score)),
+        ],
+        Event::BandEnergy(e) => vec![
+            ("energy", Floats(&e.energy)),
+            ("normalized", Floats(&e.normalized)),
+        ],
+        Event::ConstantQ(e) => vec![
+            ("magnitudes", Floats(&e.magnitudes)),
+            ("min_frequency", Float(e.min_frequency)),
+            ("bins_per_octave", Int(e.bins_per_octave as i64)),
+        ],
+        Event::Notes(e) => vec![
+            ("sounding", Notes(&e.sounding)),
+            ("onsets", Notes(&e.onsets)),
+            ("offsets", Notes(&e.offsets)),
+        ],
+    }
+}
+
+/// Estimated wall clock time the audio of the event was recorded
+pub(crate) fn capture_time(event: &Event) -> SystemTime {
+    event
+        .latency()
+        .map(|latency| latency.captured)
+        .unwrap_or_else(SystemTime::now)
+}
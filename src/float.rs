@@ -0,0 +1,73 @@
+//! Floats that keep infinity and nan in formats without them, e.g. json writes them as null.
+//! Finite values stay numbers, the others become `"inf"`, `"-inf"` or `"nan"`.
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+struct Finite(f32);
+
+impl Serialize for Finite {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            v if v.is_finite() => serializer.serialize_f32(v),
+            v if v.is_nan() => serializer.serialize_str("nan"),
+            v if v > 0.0 => serializer.serialize_str("inf"),
+            _ => serializer.serialize_str("-inf"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Float {
+    Number(f32),
+    Text(String),
+}
+
+impl Float {
+    fn value<E: Error>(self) -> Result<f32, E> {
+        match self {
+            Float::Number(v) => Ok(v),
+            Float::Text(t) => match t.as_str() {
+                "inf" => Ok(f32::INFINITY),
+                "-inf" => Ok(f32::NEG_INFINITY),
+                "nan" => Ok(f32::NAN),
+                _ => Err(E::custom(format!("invalid float {t:?}"))),
+            },
+        }
+    }
+}
+
+pub fn serialize<S: Serializer>(value: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+    Finite(*value).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    Float::deserialize(deserializer)?.value()
+}
+
+/// Lists of floats, e.g. `Vec<f32>`, `Arc<[f32]>` or `[f32; 12]`
+pub mod list {
+    use super::*;
+
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<[f32]>,
+        S: Serializer,
+    {
+        serializer.collect_seq(value.as_ref().iter().map(|v| Finite(*v)))
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: TryFrom<Vec<f32>>,
+        D: Deserializer<'de>,
+    {
+        let values = Vec::<Float>::deserialize(deserializer)?
+            .into_iter()
+            .map(Float::value)
+            .collect::<Result<Vec<f32>, _>>()?;
+        let len = values.len();
+        T::try_from(values).map_err(|_| D::Error::invalid_length(len, &"a fixed number of floats"))
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::sync::atomic::{fence, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::fields::{field_value, Value};
+use crate::{Device, Event};
+
+/// Ring of frames with a fixed width, written by one thread and copied by any number of others
+/// without locking. A copy drops the frames that were overwritten while copying.
+struct Ring {
+    width: usize,
+    frames: usize,
+    values: Box<[AtomicU32]>,
+    /// Microseconds since the start of the store for every frame, empty for raw audio
+    times: Box<[AtomicU64]>,
+    /// Frames of which writing started
+    started: AtomicUsize,
+    /// Frames that are completely written
+    written: AtomicUsize,
+}
+
+impl Ring {
+    fn new(frames: usize, width: usize, timed: bool) -> Self {
+        Self {
+            width,
+            frames,
+            values: (0..frames * width).map(|_| AtomicU32::new(0)).collect(),
+            times: (0..if timed { frames } else { 0 })
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            started: AtomicUsize::new(0),
+            written: AtomicUsize::new(0),
+        }
+    }
+
+    /// Write whole frames, a missing value of the last frame is 0
+    fn push(&self, values: &[f32], time: u64) {
+        if self.frames == 0 {
+            return;
+        }
+        let count = values.len().div_ceil(self.width);
+        let first = self.started.fetch_add(count, Ordering::Relaxed);
+        fence(Ordering::Release);
+        for frame in 0..count {
+            let index = (first + frame) % self.frames;
+            for i in 0..self.width {
+                let value = values.get(frame * self.width + i).copied().unwrap_or(0.0);
+                self.values[index * self.width + i].store(value.to_bits(), Ordering::Relaxed);
+            }
+            if let Some(t) = self.times.get(index) {
+                t.store(time, Ordering::Relaxed);
+            }
+        }
+        self.written.fetch_add(count, Ordering::Release);
+    }
+
+    /// Values and times of the frames, oldest first
+    fn copy(&self) -> (Vec<f32>, Vec<u64>) {
+        let end = self.written.load(Ordering::Acquire);
+        let begin = end.saturating_sub(self.frames);
+        let mut values = Vec::with_capacity((end - begin) * self.width);
+        let mut times = Vec::with_capacity(if self.times.is_empty() {
+            0
+        } else {
+            end - begin
+        });
+        for frame in begin..end {
+            let index = frame % self.frames;
+            let slots = &self.values[index * self.width..(index + 1) * self.width];
+            values.extend(
+                slots
+                    .iter()
+                    .map(|v| f32::from_bits(v.load(Ordering::Relaxed))),
+            );
+            if let Some(t) = self.times.get(index) {
+                times.push(t.load(Ordering::Relaxed));
+            }
+        }
+        fence(Ordering::Acquire);
+        // frames the writer may have overwritten while copying
+        let started = self.started.load(Ordering::Relaxed);
+        let overwritten = started.saturating_sub(self.frames).saturating_sub(begin);
+        let overwritten = overwritten.min(end - begin);
+        values.drain(..overwritten * self.width);
+        if !times.is_empty() {
+            times.drain(..overwritten);
+        }
+        (values, times)
+    }
+}
+
+/// Copy of a history
+#[derive(Debug, Clone, Default)]
+pub struct HistorySnapshot {
+    /// Frames of `width` values, oldest first
+    pub values: Vec<f32>,
+    /// Channels of raw audio or values of a feature field
+    pub width: usize,
+    /// Age of every frame, empty for raw audio
+    pub ages: Vec<Duration>,
+}
+
+impl HistorySnapshot {
+    pub fn frames(&self) -> impl Iterator<Item = &[f32]> {
+        self.values.chunks_exact(self.width.max(1))
+    }
+}
+
+/// A feature field kept in the history
+struct Track {
+    field: &'static str,
+    ring: Ring,
+}
+
+/// Histories of the raw samples and of feature fields for the last `duration`, shared between
+/// the thread that writes the events and render threads that take snapshots.
+/// Only one thread may write, usually the callback of `listen`.
+pub struct HistoryStore {
+    start: Instant,
+    duration: Duration,
+    raw: Ring,
+    tracks: HashMap<u32, Track>,
+}
+
+impl HistoryStore {
+    pub fn new(device: &Device, duration: Duration) -> Self {
+        let frames = (duration.as_secs_f64() * device.spec.rate as f64) as usize;
+        Self {
+            start: Instant::now(),
+            duration,
+            raw: Ring::new(frames, device.spec.channels as usize, false),
+            tracks: HashMap::new(),
+        }
+    }
+
+    /// Keep `width` values of a field of the feature, e.g. the `magnitudes` of the spectrum or the
+    /// `rms` of every channel of the level. Lists are cut or padded with 0 to the width.
+    /// The last `capacity` events are kept, at least the events the feature emits within the
+    /// duration, e.g. the duration divided by the hop of the spectrum.
+    pub fn track(mut self, flag: u32, field: &'static str, width: usize, capacity: usize) -> Self {
+        self.tracks.insert(
+            flag,
+            Track {
+                field,
+                ring: Ring::new(capacity, width.max(1), true),
+            },
+        );
+        self
+    }
+
+    pub fn write(&self, event: &Event) {
+        if let Event::Raw(raw) = event {
+            self.raw.push(&raw.data, 0);
+            return;
+        }
+        let Some(track) = self.tracks.get(&event.to_flag()) else {
+            return;
+        };
+        let time = self.start.elapsed().as_micros() as u64;
+        match field_value(event, track.field) {
+            Some(Value::Float(v)) => track.ring.push(&[v], time),
+            Some(Value::Int(v)) => track.ring.push(&[v as f32], time),
+            Some(Value::Bool(v)) => track.ring.push(&[v as u8 as f32], time),
+            Some(Value::Floats(values)) | Some(Value::Samples(values)) => {
+                let mut frame = values.to_vec();
+                frame.resize(track.ring.width, 0.0);
+                track.ring.push(&frame, time)
+            }
+            Some(Value::Ints(values)) => {
+                let mut frame: Vec<f32> = values.iter().map(|v| *v as f32).collect();
+                frame.resize(track.ring.width, 0.0);
+                track.ring.push(&frame, time)
+            }
+            _ => {}
+        }
+    }
+
+    /// Interleaved raw samples of the last duration
+    pub fn raw(&self) -> HistorySnapshot {
+        let (values, _) = self.raw.copy();
+        HistorySnapshot {
+            values,
+            width: self.raw.width,
+            ages: Vec::new(),
+        }
+    }
+
+    /// Tracked field of a feature for the last duration
+    pub fn get(&self, flag: u32) -> Option<HistorySnapshot> {
+        let track = self.tracks.get(&flag)?;
+        let (values, times) = track.ring.copy();
+        let now = self.start.elapsed();
+        let ages: Vec<Duration> = times
+            .iter()
+            .map(|t| now.saturating_sub(Duration::from_micros(*t)))
+            .collect();
+        // the ring can hold more events than the feature emits within the duration
+        let old = ages.iter().take_while(|a| **a > self.duration).count();
+        Some(HistorySnapshot {
+            values: values[old * track.ring.width..].to_vec(),
+            width: track.ring.width,
+            ages: ages[old..].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::time::SystemTime;
+
+    use pulse::sample::{Format, Spec};
+
+    use super::*;
+    use crate::feature::feature_flags;
+    use crate::latency::{self, Latency};
+    use crate::{MovingAverageEvent, RawEvent};
+
+    fn device() -> Device {
+        Device {
+            name: "test".into(),
+            spec: Spec {
+                format: Format::F32le,
+                rate: 100,
+                channels: 2,
+            },
+            monitor_of_sink_name: None,
+        }
+    }
+
+    fn latency() -> Latency {
+        Latency {
+            internal: latency::Instant::None,
+            airap: latency::Instant::None,
+            captured: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn raw_keeps_the_duration() {
+        let store = HistoryStore::new(&device(), Duration::from_millis(50));
+        let data: Vec<f32> = (0..16).map(|i| i as f32).collect();
+        store.write(&Event::Raw(RawEvent {
+            data: Cow::Owned(data),
+            latency: latency(),
+        }));
+        let raw = store.raw();
+        assert_eq!(raw.width, 2);
+        // 5 frames of 2 channels at 100 Hz
+        assert_eq!(
+            raw.values,
+            [6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 13.0, 14.0, 15.0]
+        );
+    }
+
+    #[test]
+    fn track_keeps_capacity_events() {
+        let flag = feature_flags::MOVING_AVERAGE;
+        let store =
+            HistoryStore::new(&device(), Duration::from_secs(60)).track(flag, "average", 2, 3);
+        for i in 0..5 {
+            store.write(&Event::MovingAverage(MovingAverageEvent {
+                average: vec![i as f32],
+                latency: latency(),
+            }));
+        }
+        let history = store.get(flag).unwrap();
+        // lists are padded to the width
+        assert_eq!(history.values, [2.0, 0.0, 3.0, 0.0, 4.0, 0.0]);
+        assert_eq!(history.ages.len(), 3);
+        assert!(store.get(feature_flags::PITCH).is_none());
+    }
+}
@@ -1,6 +1,10 @@
+use std::time::{Duration, SystemTime};
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MicroSeconds(pub u64);
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instant {
     /// No latency.
     None,
@@ -9,6 +13,24 @@ pub enum Instant {
     /// A negative (less than zero) amount of latency.
     Negative(MicroSeconds),
 }
+impl Instant {
+    pub fn as_secs_f32(&self) -> f32 {
+        match self {
+            Instant::None => 0.0,
+            Instant::Positive(MicroSeconds(s)) => *s as f32 / 1_000_000.0,
+            Instant::Negative(MicroSeconds(s)) => -(*s as f32) / 1_000_000.0,
+        }
+    }
+
+    /// Wall clock time this long before `time`
+    pub fn before(&self, time: SystemTime) -> SystemTime {
+        match self {
+            Instant::None => time,
+            Instant::Positive(MicroSeconds(us)) => time - Duration::from_micros(*us),
+            Instant::Negative(MicroSeconds(us)) => time + Duration::from_micros(*us),
+        }
+    }
+}
 impl From<pulse::stream::Latency> for Instant {
     fn from(value: pulse::stream::Latency) -> Self {
         match value {
@@ -19,8 +41,11 @@ impl From<pulse::stream::Latency> for Instant {
     }
 }
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Latency {
     /// Latency from recording to airap
     pub internal: Instant,
     pub airap: Instant,
+    /// Wall clock time the audio was recorded, stamped when it is read from the device
+    pub captured: SystemTime,
 }
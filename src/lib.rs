@@ -1,9 +1,11 @@
 use std::{
+    borrow::Cow,
     cell::RefCell,
-    collections::{HashMap, HashSet},
+    collections::HashSet,
     io,
     sync::{
-        mpsc::{channel, Receiver, Sender},
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
         Arc, Mutex, RwLock,
     },
     thread::{self, JoinHandle},
@@ -13,12 +15,48 @@ use std::{
 use audio::pulseaudio::raw;
 use error::AirapError;
 
+mod analysis;
 mod audio;
+mod dsp;
 pub mod feature;
+mod fields;
+#[cfg(feature = "serde")]
+mod float;
+pub mod history;
 mod latency;
+pub mod midi;
+pub mod osc;
+pub mod poll;
+pub mod recorder;
+pub mod scheduler;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "session")]
+pub mod session;
+#[cfg(feature = "async")]
+pub mod stream;
+pub use analysis::{analyze, Analysis, Series, Source};
 pub use audio::pulseaudio::Device;
-use feature::{feature_flags, Feature, FeatureStore};
+pub use feature::{
+    band::BandEnergyEvent,
+    beat::BeatEvent,
+    chroma::{ChromaEvent, KeyEvent, Mode},
+    constant_q::ConstantQEvent,
+    descriptors::SpectralDescriptorsEvent,
+    level::LevelEvent,
+    loudness::{LoudnessEvent, LoudnessReset},
+    notes::{Note, NotesEvent},
+    onset::OnsetEvent,
+    pitch::PitchEvent,
+    silence::SilenceEvent,
+    spectrum::SpectrumEvent,
+    tempo::TempoEvent,
+    voice::VoiceActivityEvent,
+};
+use feature::{feature_flags, processor, Feature, FeatureStore};
 use latency::{Instant, Latency};
+use scheduler::Node;
+pub use scheduler::{FeatureStats, SchedulerOptions};
 pub mod error;
 
 pub struct Options {
@@ -33,99 +71,166 @@ impl Default for Options {
     }
 }
 
-pub struct FeatureThread<'a> {
-    handle: JoinHandle<()>,
-    signal_tx: Sender<Event<'a>>,
-}
-pub struct FeatureThreadPool<'a, 'b> {
-    threads: HashMap<u32, FeatureThread<'a>>,
-    context: ThreadContext,
-    feature_store: &'b FeatureStore,
-    event_rx: Receiver<Event<'a>>,
+/// Runs the features on a fixed pool of workers, or inline for cheap ones, and dispatches their
+/// events. The raw audio is captured on a thread of its own.
+pub struct FeatureThreadPool {
+    nodes: Vec<Arc<Node>>,
+    capture: Option<JoinHandle<()>>,
+    workers: Vec<JoinHandle<()>>,
+    /// Closed on drop to end the workers
+    work_tx: Option<crossbeam::channel::Sender<Arc<Node>>>,
+    event_tx: Sender<Event<'static>>,
+    event_rx: Receiver<Event<'static>>,
+    stats: FeatureStats,
+    /// Set when the source of the raw audio ended
+    finished: Arc<AtomicBool>,
+    /// Set to stop dispatching and end the raw audio
+    stop: Arc<AtomicBool>,
+    /// Recorded session processed on the thread of `run` instead of the device
+    #[cfg(feature = "session")]
+    replay: Option<session::Replay>,
 }
-impl<'a: 'static, 'b> FeatureThreadPool<'a, 'b> {
-    pub fn new(context: ThreadContext, feature_store: &'b FeatureStore) -> Self {
-        let (event_tx, event_rx) = channel::<Event<'a>>();
+impl FeatureThreadPool {
+    pub fn new(context: ThreadContext, feature_store: &FeatureStore) -> Self {
+        let (event_tx, event_rx) = channel::<Event<'static>>();
+        let (work_tx, work_rx) = crossbeam::channel::unbounded();
 
-        let mut threads = HashMap::new();
+        let finished = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
 
-        if let Some(f) = feature_store.get(&feature_flags::RAW) {
-            let (signal_tx, signal_rx) = channel();
-            let event_tx = event_tx.clone();
-            let context = context.clone();
-            let handle = thread::Builder::new()
-                .name(f.to_string())
-                .spawn(move || {
-                    raw(&context.device, |e| {
-                        event_tx.send(Event::Raw(e)).unwrap();
-                    })
-                    .unwrap();
-                })
-                .unwrap();
+        #[cfg(feature = "session")]
+        let replaying = context.replay.is_some();
+        #[cfg(not(feature = "session"))]
+        let replaying = false;
 
-            threads.insert(feature_flags::RAW, FeatureThread { handle, signal_tx });
-        }
+        let capture = feature_store
+            .get(&feature_flags::RAW)
+            .filter(|_| !replaying)
+            .map(|f| {
+                let event_tx = event_tx.clone();
+                let context = context.clone();
+                let finished = finished.clone();
+                let stop = stop.clone();
+                thread::Builder::new()
+                    .name(f.to_string())
+                    .spawn(move || {
+                        raw(&context.device, &stop, |e| {
+                            // the buffer of the stream is reused once this returns
+                            let _ = event_tx.send(Event::Raw(e.into_owned()));
+                        })
+                        .unwrap();
+                        finished.store(true, Ordering::Release);
+                    })
+                    .unwrap()
+            });
 
-        if let Some(f) = feature_store.get(&feature_flags::MOVING_AVERAGE) {
-            let (signal_tx, signal_rx) = channel();
-            let event_tx = event_tx.clone();
-            let handle = thread::Builder::new()
-                .name(f.to_string())
-                .spawn(move || loop {
-                    let e = signal_rx.recv().unwrap();
-                    match e {
-                        Event::Raw(r) => {
-                            let sum: f32 = r.data.iter().sum();
-                            event_tx
-                                .send(Event::MovingAverage(MovingAverageEvent {
-                                    average: vec![sum / r.data.len() as f32],
-                                    latency: Latency {
-                                        internal: Instant::None,
-                                        airap: Instant::None,
-                                    },
-                                }))
-                                .unwrap();
-                        }
-                        Event::DefaultDeviceChange => todo!(),
-                        _ => {}
-                    }
-                })
-                .unwrap();
+        let mut features = feature_store.features();
+        // a stable order of the nodes keeps replays the same
+        features.sort_by_key(|f| f.to_flag());
+        let nodes: Vec<_> = features
+            .iter()
+            .filter_map(|f| {
+                let processor = processor(
+                    f,
+                    feature_store,
+                    &context.device.spec,
+                    &context.loudness_reset,
+                )?;
+                let inline = context.scheduler.inline & f.to_flag() > 0;
+                Some(Arc::new(Node::new(
+                    f.to_flag(),
+                    f.dependencies(),
+                    inline,
+                    processor,
+                )))
+            })
+            .collect();
 
-            threads.insert(
-                feature_flags::MOVING_AVERAGE,
-                FeatureThread { handle, signal_tx },
-            );
-        }
+        let workers = match replaying {
+            true => 0,
+            false => nodes.iter().filter(|n| !n.inline).count(),
+        };
+        let workers = (0..context.scheduler.workers.max(1).min(workers))
+            .map(|i| {
+                scheduler::spawn_worker(i, work_rx.clone(), event_tx.clone(), context.stats.clone())
+            })
+            .collect();
 
         FeatureThreadPool {
-            threads,
-            context,
-            feature_store,
+            nodes,
+            capture,
+            workers,
+            work_tx: Some(work_tx),
+            event_tx,
             event_rx,
+            stats: context.stats,
+            finished,
+            stop,
+            #[cfg(feature = "session")]
+            replay: context.replay,
         }
     }
 
+    /// Handle that makes `run` return and ends the raw audio when set
+    pub fn stop(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Dispatch events until the raw audio ended and every feature processed all of it, or until
+    /// stopped
     pub fn run<F>(&self, cb: F)
     where
         F: Fn(Event) + Send + 'static,
     {
+        #[cfg(feature = "session")]
+        if let Some(replay) = &self.replay {
+            return self.run_replay(replay, cb);
+        }
+
         loop {
-            let event = self.event_rx.recv().unwrap();
-
-            match event {
-                Event::Raw(_) => {
-                    if self.feature_store.contains(feature_flags::MOVING_AVERAGE) {
-                        self.threads
-                            .get(&feature_flags::MOVING_AVERAGE)
-                            .unwrap()
-                            .signal_tx
-                            .send(event.clone())
-                            .unwrap();
+            if self.stop.load(Ordering::Acquire) {
+                return;
+            }
+            let event = match self.event_rx.recv_timeout(Duration::from_millis(10)) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => {
+                    // nodes emit before they count an event as processed,
+                    // so once nothing is pending every event is in the channel
+                    if self.finished.load(Ordering::Acquire)
+                        && self
+                            .nodes
+                            .iter()
+                            .all(|n| n.pending.load(Ordering::Acquire) == 0)
+                    {
+                        match self.event_rx.try_recv() {
+                            Ok(event) => event,
+                            Err(_) => return,
+                        }
+                    } else {
+                        continue;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+
+            let flag = event.to_flag();
+            for node in self.nodes.iter() {
+                if node.dependencies & flag == 0 {
+                    continue;
+                }
+                if node.inline {
+                    node.process(
+                        &event,
+                        &mut |e| {
+                            let _ = self.event_tx.send(e);
+                        },
+                        &self.stats,
+                    );
+                } else if node.push(event.clone()) {
+                    if let Some(work_tx) = &self.work_tx {
+                        work_tx.send(node.clone()).unwrap();
                     }
                 }
-                Event::DefaultDeviceChange => {}
-                Event::MovingAverage(_) => {}
             }
 
             cb(event)
@@ -133,33 +238,236 @@ impl<'a: 'static, 'b> FeatureThreadPool<'a, 'b> {
     }
 }
 
+impl FeatureThreadPool {
+    /// Process the raw audio of the session and every event it leads to on this thread, every
+    /// event reaches its dependents before the events they emit. So every replay emits the same
+    /// events in the same order.
+    #[cfg(feature = "session")]
+    fn run_replay<F>(&self, replay: &session::Replay, cb: F)
+    where
+        F: Fn(Event),
+    {
+        let mut queue = std::collections::VecDeque::new();
+        replay.run(&self.finished, &self.stop, |raw| {
+            queue.push_back(raw);
+            while let Some(event) = queue.pop_front() {
+                let flag = event.to_flag();
+                for node in self.nodes.iter().filter(|n| n.dependencies & flag > 0) {
+                    node.process(&event, &mut |e| queue.push_back(e), &self.stats);
+                }
+                cb(event)
+            }
+        });
+    }
+}
+
+impl Drop for FeatureThreadPool {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        // the workers end once the work channel closes
+        self.work_tx = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        if let Some(capture) = self.capture.take() {
+            let _ = capture.join();
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RawEvent<'a> {
-    pub data: &'a [f32],
+    /// Interleaved samples, borrowed from the audio backend or owned after `into_owned`
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
+    pub data: Cow<'a, [f32]>,
     pub latency: Latency,
 }
 
+impl<'a> RawEvent<'a> {
+    pub fn into_owned(self) -> RawEvent<'static> {
+        RawEvent {
+            data: Cow::Owned(self.data.into_owned()),
+            latency: self.latency,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MovingAverageEvent {
+    #[cfg_attr(feature = "serde", serde(with = "crate::float::list"))]
     pub average: Vec<f32>,
     pub latency: Latency,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Event<'a> {
     Raw(RawEvent<'a>),
     DefaultDeviceChange,
     MovingAverage(MovingAverageEvent),
+    Tempo(TempoEvent),
+    Onset(OnsetEvent),
+    Beat(BeatEvent),
+    Pitch(PitchEvent),
+    Spectrum(SpectrumEvent),
+    Chroma(ChromaEvent),
+    Key(KeyEvent),
+    Loudness(LoudnessEvent),
+    Level(LevelEvent),
+    Silence(SilenceEvent),
+    SpectralDescriptors(SpectralDescriptorsEvent),
+    VoiceActivity(VoiceActivityEvent),
+    BandEnergy(BandEnergyEvent),
+    ConstantQ(ConstantQEvent),
+    Notes(NotesEvent),
+}
+impl<'a> Event<'a> {
+    pub fn to_flag(&self) -> u32 {
+        match self {
+            Event::Raw(_) => feature_flags::RAW,
+            Event::DefaultDeviceChange => feature_flags::DEFAULT_DEVICE_CHANGE,
+            Event::MovingAverage(_) => feature_flags::MOVING_AVERAGE,
+            Event::Tempo(_) => feature_flags::TEMPO,
+            Event::Onset(_) => feature_flags::ONSET,
+            Event::Beat(_) => feature_flags::BEAT,
+            Event::Pitch(_) => feature_flags::PITCH,
+            Event::Spectrum(_) => feature_flags::SPECTRUM,
+            Event::Chroma(_) => feature_flags::CHROMA,
+            Event::Key(_) => feature_flags::KEY,
+            Event::Loudness(_) => feature_flags::LOUDNESS,
+            Event::Level(_) => feature_flags::LEVEL,
+            Event::Silence(_) => feature_flags::SILENCE,
+            Event::SpectralDescriptors(_) => feature_flags::SPECTRAL_DESCRIPTORS,
+            Event::VoiceActivity(_) => feature_flags::VOICE_ACTIVITY,
+            Event::BandEnergy(_) => feature_flags::BAND_ENERGY,
+            Event::ConstantQ(_) => feature_flags::CONSTANT_Q,
+            Event::Notes(_) => feature_flags::NOTES,
+        }
+    }
+
+    /// Copies borrowed raw data so the event can outlive the audio buffer
+    pub fn into_owned(self) -> Event<'static> {
+        match self {
+            Event::Raw(e) => Event::Raw(e.into_owned()),
+            Event::DefaultDeviceChange => Event::DefaultDeviceChange,
+            Event::MovingAverage(e) => Event::MovingAverage(e),
+            Event::Tempo(e) => Event::Tempo(e),
+            Event::Onset(e) => Event::Onset(e),
+            Event::Beat(e) => Event::Beat(e),
+            Event::Pitch(e) => Event::Pitch(e),
+            Event::Spectrum(e) => Event::Spectrum(e),
+            Event::Chroma(e) => Event::Chroma(e),
+            Event::Key(e) => Event::Key(e),
+            Event::Loudness(e) => Event::Loudness(e),
+            Event::Level(e) => Event::Level(e),
+            Event::Silence(e) => Event::Silence(e),
+            Event::SpectralDescriptors(e) => Event::SpectralDescriptors(e),
+            Event::VoiceActivity(e) => Event::VoiceActivity(e),
+            Event::BandEnergy(e) => Event::BandEnergy(e),
+            Event::ConstantQ(e) => Event::ConstantQ(e),
+            Event::Notes(e) => Event::Notes(e),
+        }
+    }
+
+    /// Latency of the audio the event was computed from
+    pub fn latency(&self) -> Option<&Latency> {
+        match self {
+            Event::Raw(e) => Some(&e.latency),
+            Event::DefaultDeviceChange => None,
+            Event::MovingAverage(e) => Some(&e.latency),
+            Event::Tempo(e) => Some(&e.latency),
+            Event::Onset(e) => Some(&e.latency),
+            Event::Beat(e) => Some(&e.latency),
+            Event::Pitch(e) => Some(&e.latency),
+            Event::Spectrum(e) => Some(&e.latency),
+            Event::Chroma(e) => Some(&e.latency),
+            Event::Key(e) => Some(&e.latency),
+            Event::Loudness(e) => Some(&e.latency),
+            Event::Level(e) => Some(&e.latency),
+            Event::Silence(e) => Some(&e.latency),
+            Event::SpectralDescriptors(e) => Some(&e.latency),
+            Event::VoiceActivity(e) => Some(&e.latency),
+            Event::BandEnergy(e) => Some(&e.latency),
+            Event::ConstantQ(e) => Some(&e.latency),
+            Event::Notes(e) => Some(&e.latency),
+        }
+    }
+}
+
+/// Event of a single feature, received on its own with `Runner::receiver`
+pub trait FeatureEvent: Send + Sized + 'static {
+    const FLAG: u32;
+
+    fn from_event(event: Event<'static>) -> Option<Self>;
+}
+
+macro_rules! feature_event {
+    ($($event:ty => $variant:ident, $flag:ident;)*) => {
+        $(
+            impl FeatureEvent for $event {
+                const FLAG: u32 = feature_flags::$flag;
+
+                fn from_event(event: Event<'static>) -> Option<Self> {
+                    match event {
+                        Event::$variant(e) => Some(e),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+feature_event! {
+    RawEvent<'static> => Raw, RAW;
+    MovingAverageEvent => MovingAverage, MOVING_AVERAGE;
+    TempoEvent => Tempo, TEMPO;
+    OnsetEvent => Onset, ONSET;
+    BeatEvent => Beat, BEAT;
+    PitchEvent => Pitch, PITCH;
+    SpectrumEvent => Spectrum, SPECTRUM;
+    ChromaEvent => Chroma, CHROMA;
+    KeyEvent => Key, KEY;
+    LoudnessEvent => Loudness, LOUDNESS;
+    LevelEvent => Level, LEVEL;
+    SilenceEvent => Silence, SILENCE;
+    SpectralDescriptorsEvent => SpectralDescriptors, SPECTRAL_DESCRIPTORS;
+    VoiceActivityEvent => VoiceActivity, VOICE_ACTIVITY;
+    BandEnergyEvent => BandEnergy, BAND_ENERGY;
+    ConstantQEvent => ConstantQ, CONSTANT_Q;
+    NotesEvent => Notes, NOTES;
+}
+
+/// Sends the events of one feature to a typed receiver
+struct TypedSender {
+    feature: Feature,
+    send: Box<dyn Fn(&Event) + Send>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ThreadContext {
     device: Device,
+    loudness_reset: LoudnessReset,
+    scheduler: SchedulerOptions,
+    stats: FeatureStats,
+    /// Recorded session used instead of the device
+    #[cfg(feature = "session")]
+    replay: Option<session::Replay>,
 }
 
 pub struct Runner {
     device: Option<Device>,
     feature_store: FeatureStore,
+    loudness_reset: LoudnessReset,
+    scheduler: SchedulerOptions,
+    stats: FeatureStats,
+    senders: Vec<TypedSender>,
+    #[cfg(feature = "session")]
+    recording: Option<std::path::PathBuf>,
+    #[cfg(feature = "session")]
+    replay: Option<session::Replay>,
 }
 
 impl Runner {
@@ -167,9 +475,54 @@ impl Runner {
         Self {
             device: None,
             feature_store: FeatureStore::new(),
+            loudness_reset: LoudnessReset::default(),
+            scheduler: SchedulerOptions::default(),
+            stats: FeatureStats::default(),
+            senders: Vec::new(),
+            #[cfg(feature = "session")]
+            recording: None,
+            #[cfg(feature = "session")]
+            replay: None,
         }
     }
 
+    /// Record the raw audio and every event while listening to a session file
+    #[cfg(feature = "session")]
+    pub fn record<P: Into<std::path::PathBuf>>(&mut self, path: P) -> &mut Self {
+        self.recording = Some(path.into());
+        self
+    }
+
+    /// Listen to the raw audio of a recorded session instead of a device, `listen` returns when
+    /// every feature processed all of it. Without subscribed features the recorded features are used.
+    /// The features run one after the other on the thread of `listen` instead of the workers, so
+    /// every replay emits the same events in the same order.
+    #[cfg(feature = "session")]
+    pub fn replay(&mut self, session: session::Session, speed: session::ReplaySpeed) -> &mut Self {
+        self.device = Some(session.device.clone());
+        self.replay = Some(session::Replay {
+            session: Arc::new(session),
+            speed,
+        });
+        self
+    }
+
+    /// Handle for restarting the loudness measurements, can be used while listening
+    pub fn loudness_reset(&self) -> LoudnessReset {
+        self.loudness_reset.clone()
+    }
+
+    /// Workers and inline features used while listening
+    pub fn set_scheduler(&mut self, options: SchedulerOptions) -> &mut Self {
+        self.scheduler = options;
+        self
+    }
+
+    /// Handle for the processing time of every feature, can be read while listening
+    pub fn stats(&self) -> FeatureStats {
+        self.stats.clone()
+    }
+
     pub fn set_device(&mut self, device: Device) {
         self.device = Some(device);
     }
@@ -179,11 +532,91 @@ impl Runner {
         features: &[Feature],
         // features: &[impl FeatureImpl + Default + Clone + 'static],
     ) -> Result<&mut Self, AirapError> {
-        self.feature_store.set_features(features);
+        self.feature_store.set_features(features)?;
         Ok(self)
     }
 
+    /// Subscribe to `feature` as well and receive its events on their own, next to the callback of
+    /// `listen`. The events are sent until the receiver is dropped.
+    /// ```ignore
+    /// let pitch = runner.receiver::<PitchEvent>(Feature::default(feature_flags::PITCH))?;
+    /// ```
+    pub fn receiver<E: FeatureEvent>(
+        &mut self,
+        feature: Feature,
+    ) -> Result<Receiver<E>, AirapError> {
+        if feature.to_flag() != E::FLAG {
+            return Err(AirapError::feature(format!(
+                "{} does not emit the events of the receiver",
+                feature.to_string()
+            )));
+        }
+        feature.validate()?;
+        let (tx, rx) = channel();
+        self.senders.push(TypedSender {
+            feature,
+            send: Box::new(move |e| {
+                if e.to_flag() == E::FLAG {
+                    if let Some(e) = E::from_event(e.clone().into_owned()) {
+                        let _ = tx.send(e);
+                    }
+                }
+            }),
+        });
+        Ok(rx)
+    }
+
     pub fn listen<F>(&mut self, cb: F) -> Result<(), AirapError>
+    where
+        F: Fn(Event) + Send + 'static,
+    {
+        let (pool, cb) = self.start(cb)?;
+        pool.run(cb);
+        Ok(())
+    }
+
+    /// Stream of the events, listening on a thread of its own until the stream is dropped.
+    /// The events are buffered until they are polled.
+    #[cfg(feature = "async")]
+    pub fn events(&mut self) -> Result<stream::EventStream, AirapError> {
+        let (tx, rx) = futures_channel::mpsc::unbounded();
+        let (pool, cb) = self.start(move |e| {
+            let _ = tx.unbounded_send(e.into_owned());
+        })?;
+        let stop = pool.stop();
+        thread::Builder::new()
+            .name("events".into())
+            .spawn(move || pool.run(cb))?;
+        Ok(stream::EventStream::new(rx, stop))
+    }
+
+    /// Listen on a thread of its own and buffer the events, to be polled once per frame
+    pub fn poller(&mut self, options: poll::PollOptions) -> Result<poll::Poller, AirapError> {
+        let device = match &self.device {
+            Some(d) => d.clone(),
+            None => Device::default()?,
+        };
+        self.device = Some(device.clone());
+        // the raw samples of the window
+        if !self.feature_store.contains(feature_flags::RAW) {
+            let mut features = self.feature_store.features();
+            features.push(Feature::default(feature_flags::RAW));
+            self.feature_store.set_features(&features)?;
+        }
+        let (mut poller, cb) = poll::Poller::new(&device, options);
+        let (pool, cb) = self.start(cb)?;
+        poller.stop = pool.stop();
+        thread::Builder::new()
+            .name("poller".into())
+            .spawn(move || pool.run(cb))?;
+        Ok(poller)
+    }
+
+    /// Spawn the threads of the subscribed features and wrap `cb` with the session recorder
+    fn start<F>(
+        &mut self,
+        cb: F,
+    ) -> Result<(FeatureThreadPool, impl Fn(Event) + Send + 'static), AirapError>
     where
         F: Fn(Event) + Send + 'static,
     {
@@ -196,11 +629,64 @@ impl Runner {
             Device::default()?
         };
 
-        let context: ThreadContext = ThreadContext { device };
+        // features of receivers are kept when subscribing again
+        let senders = std::mem::take(&mut self.senders);
+        let missing: Vec<_> = senders
+            .iter()
+            .filter(|s| !self.feature_store.contains(s.feature.to_flag()))
+            .map(|s| s.feature.clone())
+            .collect();
+        if !missing.is_empty() {
+            let mut features = self.feature_store.features();
+            features.extend(missing);
+            self.feature_store.set_features(&features)?;
+        }
+        let cb = move |e: Event| {
+            for sender in senders.iter() {
+                (sender.send)(&e);
+            }
+            cb(e)
+        };
+
+        #[cfg(feature = "session")]
+        let cb = {
+            if let Some(replay) = &self.replay {
+                if self.feature_store.is_empty() {
+                    self.feature_store.set_features(&replay.session.features)?;
+                }
+            }
+            let recorder = match &self.recording {
+                Some(path) => {
+                    // the raw audio is needed to replay the session
+                    if !self.feature_store.contains(feature_flags::RAW) {
+                        let mut features = self.feature_store.features();
+                        features.push(Feature::default(feature_flags::RAW));
+                        self.feature_store.set_features(&features)?;
+                    }
+                    let features = self.feature_store.features();
+                    Some(session::SessionRecorder::create(path, &device, &features)?)
+                }
+                None => None,
+            };
+            move |e: Event| {
+                if let Some(recorder) = &recorder {
+                    recorder.record(&e);
+                }
+                cb(e)
+            }
+        };
+
+        let context: ThreadContext = ThreadContext {
+            device,
+            loudness_reset: self.loudness_reset.clone(),
+            scheduler: self.scheduler.clone(),
+            stats: self.stats.clone(),
+            #[cfg(feature = "session")]
+            replay: self.replay.clone(),
+        };
 
         let pool = FeatureThreadPool::new(context, &self.feature_store);
-        pool.run(move |e| cb(e));
-        Ok(())
+        Ok((pool, cb))
 
         // if let Some(feature) = self.feature_store.get_mut(Feature::RAW) {
         //     pool.add(feature.to_string(), feature)?;
@@ -0,0 +1,283 @@
+use std::time::{Duration, SystemTime};
+
+use crate::error::AirapError;
+use crate::Event;
+
+#[cfg(feature = "alsa")]
+mod alsa;
+mod file;
+#[cfg(feature = "alsa")]
+pub use self::alsa::AlsaSequencer;
+pub use file::MidiFile;
+
+/// Midi clock ticks per beat
+pub const CLOCKS_PER_BEAT: u32 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    Clock,
+    Start,
+    Stop,
+}
+
+impl MidiMessage {
+    /// Raw midi bytes of the message
+    pub fn bytes(&self) -> Vec<u8> {
+        match *self {
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => vec![0x90 | (channel & 0x0f), note & 0x7f, velocity & 0x7f],
+            MidiMessage::NoteOff { channel, note } => vec![0x80 | (channel & 0x0f), note & 0x7f, 0],
+            MidiMessage::ControlChange {
+                channel,
+                controller,
+                value,
+            } => vec![0xb0 | (channel & 0x0f), controller & 0x7f, value & 0x7f],
+            MidiMessage::Clock => vec![0xf8],
+            MidiMessage::Start => vec![0xfa],
+            MidiMessage::Stop => vec![0xfc],
+        }
+    }
+}
+
+/// Notes triggered on every beat
+#[derive(Debug, Clone)]
+pub struct BeatNotes {
+    pub channel: u8,
+    /// Note played on the first beat of a bar
+    pub downbeat: u8,
+    /// Note played on the other beats
+    pub beat: u8,
+}
+
+/// Feature values that can drive a control change, every source is scaled to 0..127
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlSource {
+    /// Normalized energy of a band
+    BandEnergy(usize),
+    /// Rms of a channel from -60 to 0 dBFS
+    Level(usize),
+    /// Momentary loudness from -60 to 0 LUFS
+    Loudness,
+    /// Speech score of the voice activity feature
+    VoiceActivity,
+    /// Spectral centroid on a logarithmic scale from 20Hz to 20kHz
+    SpectralCentroid,
+}
+
+#[derive(Debug, Clone)]
+pub struct ControlMapping {
+    pub source: ControlSource,
+    pub channel: u8,
+    pub controller: u8,
+}
+
+/// Which events get turned into which midi messages
+#[derive(Debug, Clone, Default)]
+pub struct MidiMapping {
+    /// Play the notes of the notes feature on this channel
+    pub notes: Option<u8>,
+    /// Play the voiced pitch as a monophonic line on this channel
+    pub pitch: Option<u8>,
+    pub beat: Option<BeatNotes>,
+    /// Send midi clock following the beat feature
+    pub clock: bool,
+    pub controls: Vec<ControlMapping>,
+}
+
+/// Turns events into midi messages, every message comes with a delay from the moment of the event
+pub struct MidiMapper {
+    mapping: MidiMapping,
+    pitch_note: Option<u8>,
+    beat_note: Option<u8>,
+    clock_started: bool,
+    controls: Vec<Option<u8>>,
+}
+
+impl MidiMapper {
+    pub fn new(mapping: MidiMapping) -> Self {
+        Self {
+            controls: vec![None; mapping.controls.len()],
+            mapping,
+            pitch_note: None,
+            beat_note: None,
+            clock_started: false,
+        }
+    }
+
+    pub fn map(&mut self, event: &Event) -> Vec<(Duration, MidiMessage)> {
+        let mut messages = vec![];
+        let now = Duration::ZERO;
+
+        match event {
+            Event::Notes(notes) => {
+                if let Some(channel) = self.mapping.notes {
+                    for note in notes.offsets.iter() {
+                        messages.push((
+                            now,
+                            MidiMessage::NoteOff {
+                                channel,
+                                note: note.note,
+                            },
+                        ));
+                    }
+                    for note in notes.onsets.iter() {
+                        messages.push((
+                            now,
+                            MidiMessage::NoteOn {
+                                channel,
+                                note: note.note,
+                                velocity: note.velocity,
+                            },
+                        ));
+                    }
+                }
+            }
+            Event::Pitch(pitch) => {
+                if let Some(channel) = self.mapping.pitch {
+                    let note = pitch.voiced.then_some(pitch.note);
+                    if note != self.pitch_note {
+                        if let Some(note) = self.pitch_note {
+                            messages.push((now, MidiMessage::NoteOff { channel, note }));
+                        }
+                        if let Some(note) = note {
+                            let velocity = (pitch.confidence * 127.0) as u8;
+                            messages.push((
+                                now,
+                                MidiMessage::NoteOn {
+                                    channel,
+                                    note,
+                                    velocity: velocity.max(1),
+                                },
+                            ));
+                        }
+                        self.pitch_note = note;
+                    }
+                }
+            }
+            Event::Beat(beat) => {
+                if let Some(notes) = &self.mapping.beat {
+                    if let Some(note) = self.beat_note.take() {
+                        messages.push((
+                            now,
+                            MidiMessage::NoteOff {
+                                channel: notes.channel,
+                                note,
+                            },
+                        ));
+                    }
+                    let note = if beat.bar_position == 0 {
+                        notes.downbeat
+                    } else {
+                        notes.beat
+                    };
+                    messages.push((
+                        now,
+                        MidiMessage::NoteOn {
+                            channel: notes.channel,
+                            note,
+                            velocity: 100,
+                        },
+                    ));
+                    self.beat_note = Some(note);
+                }
+                if self.mapping.clock {
+                    if !self.clock_started {
+                        self.clock_started = true;
+                        messages.push((now, MidiMessage::Start));
+                    }
+                    // spread the ticks over the predicted time until the next beat
+                    let until = beat.next.duration_since(SystemTime::now());
+                    let tick = until.unwrap_or_default() / CLOCKS_PER_BEAT;
+                    for i in 0..CLOCKS_PER_BEAT {
+                        messages.push((tick * i, MidiMessage::Clock));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        for (i, control) in self.mapping.controls.iter().enumerate() {
+            let Some(value) = control_value(control.source, event) else {
+                continue;
+            };
+            let value = (value.clamp(0.0, 1.0) * 127.0).round() as u8;
+            if self.controls[i] != Some(value) {
+                self.controls[i] = Some(value);
+                messages.push((
+                    now,
+                    MidiMessage::ControlChange {
+                        channel: control.channel,
+                        controller: control.controller,
+                        value,
+                    },
+                ));
+            }
+        }
+
+        messages
+    }
+}
+
+/// Value between 0 and 1 of `source` when `event` contains it
+fn control_value(source: ControlSource, event: &Event) -> Option<f32> {
+    let db = |db: f32| (db + 60.0) / 60.0;
+    match (source, event) {
+        (ControlSource::BandEnergy(band), Event::BandEnergy(e)) => e.normalized.get(band).cloned(),
+        (ControlSource::Level(channel), Event::Level(e)) => e.rms.get(channel).map(|v| db(*v)),
+        (ControlSource::Loudness, Event::Loudness(e)) => Some(db(e.momentary)),
+        (ControlSource::VoiceActivity, Event::VoiceActivity(e)) => Some(e.score),
+        (ControlSource::SpectralCentroid, Event::SpectralDescriptors(e)) => {
+            Some((e.centroid.max(20.0) / 20.0).log10() / 3.0)
+        }
+        _ => None,
+    }
+}
+
+/// Destination of midi messages
+pub trait MidiSink {
+    /// Send `message` after `delay`
+    fn send(&mut self, delay: Duration, message: MidiMessage) -> Result<(), AirapError>;
+}
+
+/// Maps events and sends the resulting messages to a sink
+pub struct MidiOutput<S: MidiSink> {
+    mapper: MidiMapper,
+    sink: S,
+}
+
+impl<S: MidiSink> MidiOutput<S> {
+    pub fn new(mapping: MidiMapping, sink: S) -> Self {
+        Self {
+            mapper: MidiMapper::new(mapping),
+            sink,
+        }
+    }
+
+    pub fn send(&mut self, event: &Event) -> Result<(), AirapError> {
+        for (delay, message) in self.mapper.map(event) {
+            self.sink.send(delay, message)?;
+        }
+        Ok(())
+    }
+
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+}
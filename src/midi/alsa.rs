@@ -0,0 +1,86 @@
+use std::ffi::CString;
+use std::time::Duration;
+
+use alsa::seq::{EvCtrl, EvNote, Event, EventType, PortCap, PortType, Seq};
+use alsa::Direction;
+
+use crate::error::AirapError;
+
+use super::{MidiMessage, MidiSink};
+
+/// Output port on the alsa sequencer, connect it to a synth with `aconnect` or any patchbay
+pub struct AlsaSequencer {
+    seq: Seq,
+    port: i32,
+    /// Queue used to schedule delayed messages
+    queue: i32,
+}
+
+impl AlsaSequencer {
+    pub fn new(name: &str) -> Result<Self, AirapError> {
+        let name = CString::new(name).map_err(|_| AirapError::unsupported("Invalid port name"))?;
+        let seq = Seq::open(None, Some(Direction::Playback), false)?;
+        seq.set_client_name(&name)?;
+        let port = seq.create_simple_port(
+            &name,
+            PortCap::READ | PortCap::SUBS_READ,
+            PortType::MIDI_GENERIC | PortType::APPLICATION,
+        )?;
+        let queue = seq.alloc_named_queue(&name)?;
+        seq.control_queue(queue, EventType::Start, 0, None)?;
+        seq.drain_output()?;
+        Ok(Self { seq, port, queue })
+    }
+}
+
+impl MidiSink for AlsaSequencer {
+    fn send(&mut self, delay: Duration, message: MidiMessage) -> Result<(), AirapError> {
+        let mut event = match message {
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => Event::new(
+                EventType::Noteon,
+                &EvNote {
+                    channel,
+                    note,
+                    velocity,
+                    off_velocity: 0,
+                    duration: 0,
+                },
+            ),
+            MidiMessage::NoteOff { channel, note } => Event::new(
+                EventType::Noteoff,
+                &EvNote {
+                    channel,
+                    note,
+                    velocity: 0,
+                    off_velocity: 0,
+                    duration: 0,
+                },
+            ),
+            MidiMessage::ControlChange {
+                channel,
+                controller,
+                value,
+            } => Event::new(
+                EventType::Controller,
+                &EvCtrl {
+                    channel,
+                    param: controller as u32,
+                    value: value as i32,
+                },
+            ),
+            MidiMessage::Clock => Event::new(EventType::Clock, &()),
+            MidiMessage::Start => Event::new(EventType::Start, &()),
+            MidiMessage::Stop => Event::new(EventType::Stop, &()),
+        };
+        event.set_source(self.port);
+        event.set_subs();
+        event.schedule_real(self.queue, true, delay);
+        self.seq.event_output(&mut event)?;
+        self.seq.drain_output()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::error::AirapError;
+
+use super::{MidiMessage, MidiSink};
+
+/// Ticks per quarter note
+const DIVISION: u16 = 480;
+/// Microseconds per quarter note, 120 bpm so a tick is a fixed amount of real time
+const TEMPO: u32 = 500_000;
+
+/// Standard midi file (format 0) of every message sent, timed by the wall clock
+pub struct MidiFile {
+    writer: BufWriter<File>,
+    start: Instant,
+    /// Message and its time since `start`
+    messages: Vec<(Duration, MidiMessage)>,
+}
+
+impl MidiFile {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, AirapError> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            start: Instant::now(),
+            messages: vec![],
+        })
+    }
+
+    /// Write the file, nothing is written before this is called. Notes still sounding are stopped
+    /// at the time of the last message.
+    pub fn finish(mut self) -> Result<(), AirapError> {
+        self.messages.sort_by_key(|(time, _)| *time);
+        let mut sounding = vec![];
+        for (_, message) in self.messages.iter() {
+            match *message {
+                MidiMessage::NoteOn {
+                    channel,
+                    note,
+                    velocity,
+                } if velocity > 0 => sounding.push((channel, note)),
+                MidiMessage::NoteOn { channel, note, .. }
+                | MidiMessage::NoteOff { channel, note } => {
+                    sounding.retain(|n| *n != (channel, note))
+                }
+                _ => {}
+            }
+        }
+        let end = self
+            .messages
+            .last()
+            .map_or(Duration::ZERO, |(time, _)| *time);
+        for (channel, note) in sounding {
+            self.messages
+                .push((end, MidiMessage::NoteOff { channel, note }));
+        }
+
+        let mut track = vec![];
+        // tempo meta event
+        track.extend_from_slice(&[0x00, 0xff, 0x51, 0x03]);
+        track.extend_from_slice(&TEMPO.to_be_bytes()[1..]);
+        let tick = Duration::from_micros(TEMPO as u64) / DIVISION as u32;
+        let mut previous = 0;
+        for (time, message) in self.messages.iter() {
+            let ticks = (time.as_nanos() / tick.as_nanos()) as u32;
+            write_variable_length(&mut track, ticks - previous);
+            previous = ticks;
+            track.extend(message.bytes());
+        }
+        // end of track
+        track.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+        let writer = &mut self.writer;
+        writer.write_all(b"MThd")?;
+        writer.write_all(&6u32.to_be_bytes())?;
+        // format 0, a single track
+        writer.write_all(&0u16.to_be_bytes())?;
+        writer.write_all(&1u16.to_be_bytes())?;
+        writer.write_all(&DIVISION.to_be_bytes())?;
+        writer.write_all(b"MTrk")?;
+        writer.write_all(&(track.len() as u32).to_be_bytes())?;
+        writer.write_all(&track)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl MidiSink for MidiFile {
+    fn send(&mut self, delay: Duration, message: MidiMessage) -> Result<(), AirapError> {
+        self.messages.push((self.start.elapsed() + delay, message));
+        Ok(())
+    }
+}
+
+fn write_variable_length(buffer: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.extend(bytes.iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sounding_notes_stopped() {
+        let path = std::env::temp_dir().join(format!("airap-{}.mid", std::process::id()));
+        let mut file = MidiFile::create(&path).unwrap();
+        let on = |note| MidiMessage::NoteOn {
+            channel: 1,
+            note,
+            velocity: 100,
+        };
+        file.send(Duration::ZERO, on(60)).unwrap();
+        file.send(Duration::ZERO, on(64)).unwrap();
+        let off = MidiMessage::NoteOff {
+            channel: 1,
+            note: 60,
+        };
+        file.send(Duration::from_millis(500), off).unwrap();
+        file.finish().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // 64 is stopped at the same tick as the last message, right before the end of track
+        assert!(
+            bytes.ends_with(&[0x00, 0x81, 64, 0, 0x00, 0xff, 0x2f, 0x00]),
+            "{bytes:x?}"
+        );
+    }
+}
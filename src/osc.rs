@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::AirapError;
+use crate::feature::Feature;
+use crate::fields::{capture_time, fields, Value};
+use crate::Event;
+
+/// Seconds between the ntp epoch (1900) and the unix epoch (1970)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArgument {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Blob(Vec<u8>),
+    Bool(bool),
+    /// Ntp timestamp like the timetag of a bundle
+    TimeTag(u64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub arguments: Vec<OscArgument>,
+}
+
+impl OscMessage {
+    pub fn new<S: Into<String>>(address: S, arguments: Vec<OscArgument>) -> Self {
+        Self {
+            address: address.into(),
+            arguments,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        write_string(&mut buffer, &self.address);
+        let tags: String = std::iter::once(',')
+            .chain(self.arguments.iter().map(|a| match a {
+                OscArgument::Int(_) => 'i',
+                OscArgument::Float(_) => 'f',
+                OscArgument::String(_) => 's',
+                OscArgument::Blob(_) => 'b',
+                OscArgument::Bool(true) => 'T',
+                OscArgument::Bool(false) => 'F',
+                OscArgument::TimeTag(_) => 't',
+            }))
+            .collect();
+        write_string(&mut buffer, &tags);
+        for argument in self.arguments.iter() {
+            match argument {
+                OscArgument::Int(i) => buffer.extend_from_slice(&i.to_be_bytes()),
+                OscArgument::Float(f) => buffer.extend_from_slice(&f.to_be_bytes()),
+                OscArgument::String(s) => write_string(&mut buffer, s),
+                OscArgument::Blob(b) => {
+                    buffer.extend_from_slice(&(b.len() as i32).to_be_bytes());
+                    buffer.extend_from_slice(b);
+                    pad(&mut buffer);
+                }
+                OscArgument::Bool(_) => {}
+                OscArgument::TimeTag(t) => buffer.extend_from_slice(&t.to_be_bytes()),
+            }
+        }
+        buffer
+    }
+}
+
+/// Bundle of messages that should be handled at `timetag`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscBundle {
+    /// Ntp timestamp, seconds since 1900 in the upper 32 bits and the fraction in the lower
+    pub timetag: u64,
+    pub messages: Vec<OscMessage>,
+}
+
+impl OscBundle {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        write_string(&mut buffer, "#bundle");
+        buffer.extend_from_slice(&self.timetag.to_be_bytes());
+        for message in self.messages.iter() {
+            let message = message.encode();
+            buffer.extend_from_slice(&(message.len() as i32).to_be_bytes());
+            buffer.extend(message);
+        }
+        buffer
+    }
+}
+
+/// Null terminated and padded to a multiple of 4 bytes
+fn write_string(buffer: &mut Vec<u8>, s: &str) {
+    buffer.extend_from_slice(s.as_bytes());
+    buffer.push(0);
+    pad(buffer);
+}
+
+fn pad(buffer: &mut Vec<u8>) {
+    while !buffer.len().is_multiple_of(4) {
+        buffer.push(0);
+    }
+}
+
+/// Ntp timetag of `time`
+pub fn timetag(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+    let seconds = since_epoch.as_secs() + NTP_UNIX_OFFSET;
+    let fraction = (since_epoch.subsec_nanos() as u64 * (1 << 32)) / 1_000_000_000;
+    (seconds << 32) | fraction
+}
+
+#[derive(Debug, Clone)]
+pub struct OscOptions {
+    /// Prefix of every address, events are sent to `{prefix}/{feature}/{field}`
+    pub prefix: String,
+    /// Replaces `{prefix}/{feature}` for the feature flag
+    pub addresses: HashMap<u32, String>,
+    /// Send all fields of an event in a single bundle timed at the capture of the audio,
+    /// otherwise every field is sent as a separate message to be handled immediately
+    pub bundle: bool,
+}
+
+impl Default for OscOptions {
+    fn default() -> Self {
+        Self {
+            prefix: "/airap".into(),
+            addresses: HashMap::new(),
+            bundle: true,
+        }
+    }
+}
+
+/// Sends events as osc messages over udp
+pub struct OscSender {
+    socket: UdpSocket,
+    target: SocketAddr,
+    options: OscOptions,
+}
+
+impl OscSender {
+    pub fn new<A: ToSocketAddrs>(target: A, options: OscOptions) -> Result<Self, AirapError> {
+        let target = target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| AirapError::unsupported("No address to send osc messages to"))?;
+        let local: SocketAddr = if target.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        Ok(Self {
+            socket: UdpSocket::bind(local)?,
+            target,
+            options,
+        })
+    }
+
+    pub fn send(&self, event: &Event) -> Result<(), AirapError> {
+        let messages = self.messages(event);
+        if self.options.bundle {
+            let bundle = OscBundle {
+                timetag: timetag(capture_time(event)),
+                messages,
+            };
+            self.socket.send_to(&bundle.encode(), self.target)?;
+        } else {
+            for message in messages {
+                self.socket.send_to(&message.encode(), self.target)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn address(&self, event: &Event) -> String {
+        let flag = event.to_flag();
+        match self.options.addresses.get(&flag) {
+            Some(address) => address.clone(),
+            None => format!(
+                "{}/{}",
+                self.options.prefix,
+                Feature::default(flag).to_string()
+            ),
+        }
+    }
+
+    /// A message for every field of the event
+    pub fn messages(&self, event: &Event) -> Vec<OscMessage> {
+        let address = self.address(event);
+        let fields = fields(event);
+        if fields.is_empty() {
+            return vec![OscMessage::new(address, vec![])];
+        }
+
+        fields
+            .into_iter()
+            .map(|(field, value)| {
+                let arguments = match value {
+                    Value::Bool(b) => vec![OscArgument::Bool(b)],
+                    Value::Int(i) => vec![OscArgument::Int(i as i32)],
+                    Value::Float(f) => vec![OscArgument::Float(f)],
+                    Value::Text(s) => vec![OscArgument::String(s.into())],
+                    Value::Time(t) => vec![OscArgument::TimeTag(timetag(t))],
+                    Value::Floats(values) => {
+                        values.iter().map(|v| OscArgument::Float(*v)).collect()
+                    }
+                    Value::Ints(values) => {
+                        values.iter().map(|v| OscArgument::Int(*v as i32)).collect()
+                    }
+                    // note and velocity pairs
+                    Value::Notes(notes) => notes
+                        .iter()
+                        .flat_map(|n| {
+                            [
+                                OscArgument::Int(n.note as i32),
+                                OscArgument::Int(n.velocity as i32),
+                            ]
+                        })
+                        .collect(),
+                    Value::Samples(samples) => vec![OscArgument::Blob(
+                        samples.iter().flat_map(|s| s.to_be_bytes()).collect(),
+                    )],
+                };
+                OscMessage::new(format!("{address}/{field}"), arguments)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::beat::BeatEvent;
+    use crate::feature::silence::SilenceEvent;
+    use crate::latency::{Instant, Latency, MicroSeconds};
+
+    #[test]
+    fn bundle_is_timed_at_capture() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let sender = OscSender::new(socket.local_addr().unwrap(), OscOptions::default()).unwrap();
+
+        let captured = UNIX_EPOCH + Duration::from_millis(1_700_000_000_250);
+        let event = Event::Silence(SilenceEvent {
+            silent: true,
+            level: -72.5,
+            previous: Duration::from_secs(3),
+            latency: Latency {
+                internal: Instant::Positive(MicroSeconds(20_000)),
+                airap: Instant::None,
+                captured,
+            },
+        });
+        sender.send(&event).unwrap();
+
+        let mut buffer = [0; 1024];
+        let (len, _) = socket.recv_from(&mut buffer).unwrap();
+        let expected = OscBundle {
+            timetag: timetag(captured),
+            messages: sender.messages(&event),
+        };
+        assert_eq!(&buffer[..len], expected.encode().as_slice());
+        assert_eq!(&buffer[..8], b"#bundle\0");
+        let timetag = u64::from_be_bytes(buffer[8..16].try_into().unwrap());
+        assert_eq!(timetag >> 32, 1_700_000_000 + NTP_UNIX_OFFSET);
+        assert_eq!(timetag & 0xffff_ffff, 1 << 30);
+    }
+
+    #[test]
+    fn beat_next_is_timetag() {
+        let sender = OscSender::new("127.0.0.1:9", OscOptions::default()).unwrap();
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        let event = Event::Beat(BeatEvent {
+            index: 3,
+            bar_position: 3,
+            bpm: 120.0,
+            next: time,
+            latency: Latency {
+                internal: Instant::None,
+                airap: Instant::None,
+                captured: UNIX_EPOCH,
+            },
+        });
+        let messages = sender.messages(&event);
+        let next = messages
+            .iter()
+            .find(|m| m.address == "/airap/beat/next")
+            .unwrap();
+        assert_eq!(next.arguments, [OscArgument::TimeTag(timetag(time))]);
+        let encoded = next.encode();
+        assert_eq!(&encoded[encoded.len() - 12..encoded.len() - 8], b",t\0\0");
+    }
+}
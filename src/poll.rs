@@ -0,0 +1,131 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::feature::feature_flags;
+use crate::{Device, Event};
+
+#[derive(Debug, Clone)]
+pub struct PollOptions {
+    /// Length of the raw sample window and of the feature history
+    pub window: Duration,
+    /// Features of which the events are buffered and kept in the history
+    pub flags: u32,
+    /// Events buffered between two polls, the oldest are dropped when there are more
+    pub capacity: usize,
+}
+
+impl Default for PollOptions {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_millis(200),
+            flags: !feature_flags::RAW,
+            capacity: 1024,
+        }
+    }
+}
+
+struct PollState {
+    /// Events since the last poll
+    events: VecDeque<Event<'static>>,
+    /// Interleaved raw samples of the last window
+    samples: VecDeque<f32>,
+    /// Events of each feature within the last window and when they arrived
+    history: HashMap<u32, VecDeque<(Instant, Event<'static>)>>,
+}
+
+/// Buffers the events of a `Runner` for render loops and other frame synchronous consumers,
+/// returned by `Runner::poller`. Dropping it stops the audio and the feature threads.
+pub struct Poller {
+    state: Arc<Mutex<PollState>>,
+    options: PollOptions,
+    /// Stops the pool of the runner
+    pub(crate) stop: Arc<AtomicBool>,
+}
+
+impl Poller {
+    /// Poller and the callback that fills its buffers
+    pub(crate) fn new(
+        device: &Device,
+        options: PollOptions,
+    ) -> (Self, impl Fn(Event) + Send + 'static) {
+        let samples = (options.window.as_secs_f64() * device.spec.rate as f64) as usize
+            * device.spec.channels as usize;
+        let state = Arc::new(Mutex::new(PollState {
+            events: VecDeque::with_capacity(options.capacity),
+            samples: VecDeque::with_capacity(samples),
+            history: HashMap::new(),
+        }));
+
+        let shared = state.clone();
+        let (window, flags, capacity) = (options.window, options.flags, options.capacity);
+        let cb = move |e: Event| {
+            let mut state = shared.lock().unwrap();
+            if let Event::Raw(raw) = &e {
+                state.samples.extend(raw.data.iter());
+                let excess = state.samples.len().saturating_sub(samples);
+                state.samples.drain(..excess);
+            }
+            let flag = e.to_flag();
+            if flag & flags == 0 {
+                return;
+            }
+            let e = e.into_owned();
+            if state.events.len() == capacity {
+                state.events.pop_front();
+            }
+            state.events.push_back(e.clone());
+            let now = Instant::now();
+            let history = state.history.entry(flag).or_default();
+            history.push_back((now, e));
+            trim(history, now, window);
+        };
+
+        (
+            Self {
+                state,
+                options,
+                stop: Arc::default(),
+            },
+            cb,
+        )
+    }
+
+    /// Every buffered event since the last poll
+    pub fn poll(&self) -> Vec<Event<'static>> {
+        self.state.lock().unwrap().events.drain(..).collect()
+    }
+
+    /// Interleaved raw samples of the last window, fewer at the start
+    pub fn window(&self) -> Vec<f32> {
+        self.state.lock().unwrap().samples.iter().cloned().collect()
+    }
+
+    /// Events of a feature that arrived within the last window, oldest first
+    pub fn history(&self, flag: u32) -> Vec<Event<'static>> {
+        let mut state = self.state.lock().unwrap();
+        match state.history.get_mut(&flag) {
+            Some(history) => {
+                trim(history, Instant::now(), self.options.window);
+                history.iter().map(|(_, e)| e.clone()).collect()
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+    }
+}
+
+fn trim(history: &mut VecDeque<(Instant, Event<'static>)>, now: Instant, window: Duration) {
+    while history
+        .front()
+        .is_some_and(|(time, _)| now.duration_since(*time) > window)
+    {
+        history.pop_front();
+    }
+}
@@ -0,0 +1,225 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::info;
+
+use crate::dsp::Biquad;
+use crate::error::AirapError;
+use crate::{Device, Event};
+
+mod flac;
+mod preroll;
+mod wav;
+
+use flac::FlacWriter;
+pub use preroll::{PreRoll, Snapshot, SnapshotTrigger};
+use wav::WavWriter;
+
+/// Low pass before down sampling, relative to the new nyquist frequency
+const ANTI_ALIAS: f32 = 0.9;
+
+trait AudioWriter: Send {
+    /// Write interleaved samples
+    fn write(&mut self, samples: &[f32]) -> Result<(), AirapError>;
+    /// Size of the file so far
+    fn bytes(&self) -> u64;
+    /// Whether this many more samples fit in the file, the recorder starts a new file otherwise
+    fn fits(&self, _samples: usize) -> bool {
+        true
+    }
+    fn finish(self: Box<Self>) -> Result<(), AirapError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    /// 32 bit float
+    Wav,
+    /// 16 bit lossless
+    Flac,
+}
+
+impl AudioFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Flac => "flac",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecorderOptions {
+    pub format: AudioFormat,
+    /// Keep every nth frame after a low pass, 1 keeps the rate of the device
+    pub down_sampling: u32,
+    /// Start a new file when the current one reaches this many bytes, wav files also start a new
+    /// file before reaching the 4 GiB their header can describe
+    pub max_size: Option<u64>,
+    /// Start a new file when the current one reaches this duration
+    pub max_duration: Option<Duration>,
+    /// Stop writing while the silence feature reports silence and start a new file when the signal
+    /// comes back, requires the silence feature to be subscribed
+    pub silence_gate: bool,
+}
+
+impl Default for RecorderOptions {
+    fn default() -> Self {
+        Self {
+            format: AudioFormat::Wav,
+            down_sampling: 1,
+            max_size: None,
+            max_duration: None,
+            silence_gate: false,
+        }
+    }
+}
+
+/// Writes the raw events to audio files, named after `path` with `-{index}` added to the file
+/// stem and the extension of the format
+pub struct Recorder {
+    path: PathBuf,
+    options: RecorderOptions,
+    channels: usize,
+    /// Rate after down sampling
+    rate: u32,
+    writer: Option<Box<dyn AudioWriter>>,
+    /// Frames in the current file
+    frames: u64,
+    index: usize,
+    filters: Vec<[Biquad; 2]>,
+    /// Frames until the next frame that is kept when down sampling
+    skip: u32,
+    silent: bool,
+}
+
+impl Recorder {
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        device: &Device,
+        options: RecorderOptions,
+    ) -> Result<Self, AirapError> {
+        if options.down_sampling == 0 {
+            return Err(AirapError::feature("Down sampling must be at least 1"));
+        }
+        let rate = device.spec.rate / options.down_sampling;
+        let channels = device.spec.channels as usize;
+        // two butterworth sections for a steeper cutoff
+        let cutoff = ANTI_ALIAS * rate as f32 / 2.0;
+        let filter = || Biquad::low_pass(cutoff, device.spec.rate as f32);
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            channels,
+            rate,
+            writer: None,
+            frames: 0,
+            index: 0,
+            filters: (0..channels).map(|_| [filter(), filter()]).collect(),
+            skip: 0,
+            silent: false,
+            options,
+        })
+    }
+
+    pub fn write(&mut self, event: &Event) -> Result<(), AirapError> {
+        match event {
+            Event::Raw(raw) => {
+                if self.options.silence_gate && self.silent {
+                    return Ok(());
+                }
+                let samples = self.down_sample(&raw.data);
+                self.write_samples(&samples)
+            }
+            Event::Silence(silence) if self.options.silence_gate => {
+                self.silent = silence.silent;
+                if silence.silent {
+                    self.close()?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Close the current file
+    pub fn finish(mut self) -> Result<(), AirapError> {
+        self.close()
+    }
+
+    fn down_sample(&mut self, data: &[f32]) -> Vec<f32> {
+        if self.options.down_sampling == 1 {
+            return data.to_vec();
+        }
+        let mut samples = Vec::with_capacity(data.len() / self.options.down_sampling as usize + 1);
+        for frame in data.chunks_exact(self.channels) {
+            let filtered: Vec<f32> = frame
+                .iter()
+                .zip(self.filters.iter_mut())
+                .map(|(x, [a, b])| b.process(a.process(*x)))
+                .collect();
+            if self.skip == 0 {
+                samples.extend(filtered);
+                self.skip = self.options.down_sampling;
+            }
+            self.skip -= 1;
+        }
+        samples
+    }
+
+    fn write_samples(&mut self, samples: &[f32]) -> Result<(), AirapError> {
+        for frame in samples.chunks(self.channels) {
+            let full_size = matches!(
+                (&self.writer, self.options.max_size),
+                (Some(w), Some(max)) if w.bytes() >= max
+            ) || matches!(&self.writer, Some(w) if !w.fits(frame.len()));
+            let full_duration = matches!(
+                self.options.max_duration,
+                Some(max) if self.writer.is_some()
+                    && Duration::from_secs_f64(self.frames as f64 / self.rate as f64) >= max
+            );
+            if full_size || full_duration {
+                self.close()?;
+            }
+            if self.writer.is_none() {
+                self.open()?;
+            }
+            self.writer.as_mut().unwrap().write(frame)?;
+            self.frames += 1;
+        }
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<(), AirapError> {
+        self.index += 1;
+        let stem = self
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "recording".into());
+        let path = self.path.with_file_name(format!(
+            "{stem}-{}.{}",
+            self.index,
+            self.options.format.extension()
+        ));
+        info!("Recording to {path:?}");
+        let channels = self.channels as u16;
+        self.writer = Some(match self.options.format {
+            AudioFormat::Wav => Box::new(WavWriter::create(&path, channels, self.rate)?),
+            AudioFormat::Flac => Box::new(FlacWriter::create(&path, channels, self.rate)?),
+        });
+        self.frames = 0;
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), AirapError> {
+        match self.writer.take() {
+            Some(writer) => writer.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
@@ -0,0 +1,268 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::error::AirapError;
+
+use super::AudioWriter;
+
+/// Samples per channel in a frame
+const BLOCK_SIZE: usize = 4096;
+const BITS_PER_SAMPLE: u64 = 16;
+/// Offset of the sample rate, channels, bits per sample and total samples in the file
+const STREAMINFO_SAMPLES: u64 = 18;
+/// Largest rice parameter without using the escape code
+const MAX_RICE_PARAMETER: u32 = 14;
+
+/// 16 bit flac using the fixed predictors and a single rice partition
+pub struct FlacWriter {
+    writer: BufWriter<File>,
+    channels: usize,
+    rate: u32,
+    /// Interleaved samples of the next frame
+    block: Vec<i32>,
+    frames: u32,
+    /// Samples per channel written
+    samples: u64,
+    bytes: u64,
+}
+
+impl FlacWriter {
+    pub fn create(path: &Path, channels: u16, rate: u32) -> Result<Self, AirapError> {
+        if !(1..=8).contains(&channels) {
+            return Err(AirapError::unsupported(
+                "Flac supports between 1 and 8 channels",
+            ));
+        }
+        let mut flac = Self {
+            writer: BufWriter::new(File::create(path)?),
+            channels: channels as usize,
+            rate,
+            block: Vec::with_capacity(BLOCK_SIZE * channels as usize),
+            frames: 0,
+            samples: 0,
+            bytes: 0,
+        };
+
+        let mut header = b"fLaC".to_vec();
+        // last metadata block, streaminfo, length
+        header.extend_from_slice(&[0x80, 0x00, 0x00, 34]);
+        header.extend_from_slice(&(BLOCK_SIZE as u16).to_be_bytes());
+        header.extend_from_slice(&(BLOCK_SIZE as u16).to_be_bytes());
+        // unknown minimum and maximum frame size
+        header.extend_from_slice(&[0; 6]);
+        header.extend_from_slice(&flac.stream_info().to_be_bytes());
+        // no md5 signature
+        header.extend_from_slice(&[0; 16]);
+        flac.writer.write_all(&header)?;
+        flac.bytes = header.len() as u64;
+        Ok(flac)
+    }
+
+    /// Sample rate, channels, bits per sample and total samples
+    fn stream_info(&self) -> u64 {
+        (self.rate as u64) << 44
+            | (self.channels as u64 - 1) << 41
+            | (BITS_PER_SAMPLE - 1) << 36
+            | self.samples
+    }
+
+    fn write_frame(&mut self) -> Result<(), AirapError> {
+        let size = self.block.len() / self.channels;
+        if size == 0 {
+            return Ok(());
+        }
+
+        let mut bits = BitWriter::default();
+        bits.write(0b11111111111110, 14);
+        // reserved, fixed block size
+        bits.write(0, 2);
+        // block size as 16 bit at the end of the header, sample rate from streaminfo
+        bits.write(0b0111, 4);
+        bits.write(0b0000, 4);
+        // independent channels, 16 bit samples, reserved
+        bits.write(self.channels as u64 - 1, 4);
+        bits.write(0b100, 3);
+        bits.write(0, 1);
+        for byte in utf8(self.frames) {
+            bits.write(byte as u64, 8);
+        }
+        bits.write(size as u64 - 1, 16);
+        let crc = crc8(&bits.bytes);
+        bits.write(crc as u64, 8);
+
+        for channel in 0..self.channels {
+            let samples: Vec<i32> = self
+                .block
+                .iter()
+                .skip(channel)
+                .step_by(self.channels)
+                .cloned()
+                .collect();
+            subframe(&mut bits, &samples);
+        }
+        bits.align();
+        let crc = crc16(&bits.bytes);
+        bits.write(crc as u64, 16);
+
+        self.writer.write_all(&bits.bytes)?;
+        self.bytes += bits.bytes.len() as u64;
+        self.samples += size as u64;
+        self.frames += 1;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+impl AudioWriter for FlacWriter {
+    fn write(&mut self, samples: &[f32]) -> Result<(), AirapError> {
+        for s in samples {
+            self.block
+                .push((s.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i32);
+            if self.block.len() == BLOCK_SIZE * self.channels {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), AirapError> {
+        self.write_frame()?;
+        self.writer.flush()?;
+        let stream_info = self.stream_info();
+        let file = self.writer.get_mut();
+        file.seek(SeekFrom::Start(STREAMINFO_SAMPLES))?;
+        file.write_all(&stream_info.to_be_bytes())?;
+        Ok(())
+    }
+}
+
+/// Fixed predictor subframe with the order giving the smallest residual
+fn subframe(bits: &mut BitWriter, samples: &[i32]) {
+    let residual = |order: usize| -> Vec<i32> {
+        (order..samples.len())
+            .map(|n| {
+                let x = |i: usize| samples[n - i];
+                match order {
+                    0 => x(0),
+                    1 => x(0) - x(1),
+                    2 => x(0) - 2 * x(1) + x(2),
+                    3 => x(0) - 3 * x(1) + 3 * x(2) - x(3),
+                    _ => x(0) - 4 * x(1) + 6 * x(2) - 4 * x(3) + x(4),
+                }
+            })
+            .collect()
+    };
+    let (order, residual) = (0..=4.min(samples.len() - 1))
+        .map(|order| (order, residual(order)))
+        .min_by_key(|(_, r)| r.iter().map(|r| r.unsigned_abs() as u64).sum::<u64>())
+        .unwrap();
+
+    // zero bit, fixed subframe of the order, no wasted bits
+    bits.write(0, 1);
+    bits.write(0b001000 | order as u64, 6);
+    bits.write(0, 1);
+    for s in &samples[..order] {
+        bits.write(*s as u16 as u64, BITS_PER_SAMPLE as u32);
+    }
+
+    let zigzag: Vec<u32> = residual
+        .iter()
+        .map(|r| ((r << 1) ^ (r >> 31)) as u32)
+        .collect();
+    let mean = zigzag.iter().map(|u| *u as u64).sum::<u64>() / zigzag.len().max(1) as u64;
+    let parameter = if mean > 0 {
+        (63 - mean.leading_zeros()).min(MAX_RICE_PARAMETER)
+    } else {
+        0
+    };
+    // rice coding with a 4 bit parameter, a single partition
+    bits.write(0b00, 2);
+    bits.write(0, 4);
+    bits.write(parameter as u64, 4);
+    for u in zigzag {
+        for _ in 0..(u >> parameter) {
+            bits.write(0, 1);
+        }
+        bits.write(1, 1);
+        bits.write((u & ((1 << parameter) - 1)) as u64, parameter);
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits used of the last byte, 0 when it is full
+    used: u32,
+}
+
+impl BitWriter {
+    /// Write the lowest `count` bits of `value`, most significant first
+    fn write(&mut self, value: u64, count: u32) {
+        for i in (0..count).rev() {
+            if self.used == 0 {
+                self.bytes.push(0);
+            }
+            let bit = ((value >> i) & 1) as u8;
+            *self.bytes.last_mut().unwrap() |= bit << (7 - self.used);
+            self.used = (self.used + 1) % 8;
+        }
+    }
+
+    fn align(&mut self) {
+        self.used = 0;
+    }
+}
+
+/// Frame number in the utf-8 like coding of flac
+fn utf8(value: u32) -> Vec<u8> {
+    if value < 0x80 {
+        return vec![value as u8];
+    }
+    let mut continuation = vec![];
+    let mut value = value;
+    // bits left for the first byte shrink with every continuation byte
+    let mut first_bits = 6;
+    while value >= 1 << first_bits {
+        continuation.push(0x80 | (value & 0x3f) as u8);
+        value >>= 6;
+        first_bits -= 1;
+    }
+    let length = continuation.len() + 1;
+    let prefix = !(0xffu8 >> length);
+    let mut bytes = vec![prefix | value as u8];
+    bytes.extend(continuation.iter().rev());
+    bytes
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |mut crc, byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |mut crc, byte| {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
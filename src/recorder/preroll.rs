@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::AirapError;
+use crate::{Device, Event};
+
+use super::flac::FlacWriter;
+use super::wav::WavWriter;
+use super::{AudioFormat, AudioWriter};
+
+/// Fires a snapshot of a `PreRoll` from any thread
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotTrigger(Arc<AtomicBool>);
+
+impl SnapshotTrigger {
+    pub fn fire(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Audio around a trigger
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// Interleaved samples
+    pub samples: Vec<f32>,
+    pub channels: u16,
+    pub rate: u32,
+    /// Frame at which the trigger fired
+    pub trigger: usize,
+}
+
+impl Snapshot {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.samples.len() as f64 / self.channels as f64 / self.rate as f64)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: AudioFormat) -> Result<(), AirapError> {
+        let path = path.as_ref();
+        let mut writer: Box<dyn AudioWriter> = match format {
+            AudioFormat::Wav => Box::new(WavWriter::create(path, self.channels, self.rate)?),
+            AudioFormat::Flac => Box::new(FlacWriter::create(path, self.channels, self.rate)?),
+        };
+        writer.write(&self.samples)?;
+        writer.finish()
+    }
+}
+
+/// A snapshot waiting for its post trigger audio
+struct Capture {
+    samples: Vec<f32>,
+    trigger: usize,
+    /// Samples still needed after the trigger
+    remaining: usize,
+}
+
+type Predicate = Box<dyn Fn(&Event) -> bool + Send>;
+
+/// Rolling history of the raw audio, taking a snapshot of it with some audio after the trigger
+/// when it fires by hand or by a predicate on any event
+pub struct PreRoll {
+    history: VecDeque<f32>,
+    /// Samples kept before a trigger
+    pre: usize,
+    /// Samples added after a trigger
+    post: usize,
+    channels: u16,
+    rate: u32,
+    predicate: Option<Predicate>,
+    trigger: SnapshotTrigger,
+    capture: Option<Capture>,
+}
+
+impl PreRoll {
+    pub fn new(device: &Device, pre: Duration, post: Duration) -> Self {
+        let channels = device.spec.channels as u16;
+        let rate = device.spec.rate;
+        let samples = |d: Duration| (d.as_secs_f64() * rate as f64) as usize * channels as usize;
+        Self {
+            history: VecDeque::with_capacity(samples(pre)),
+            pre: samples(pre),
+            post: samples(post),
+            channels,
+            rate,
+            predicate: None,
+            trigger: SnapshotTrigger::default(),
+            capture: None,
+        }
+    }
+
+    /// Fire when `predicate` is true for an event, e.g. a loudness above a threshold
+    pub fn with_predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Event) -> bool + Send + 'static,
+    {
+        self.predicate = Some(Box::new(predicate));
+        self
+    }
+
+    pub fn trigger(&self) -> SnapshotTrigger {
+        self.trigger.clone()
+    }
+
+    /// Feed an event, returns the snapshot once the audio after the trigger is complete.
+    /// Triggers while a snapshot is being completed are ignored.
+    pub fn write(&mut self, event: &Event) -> Option<Snapshot> {
+        let fired = self.trigger.take() || self.predicate.as_ref().is_some_and(|p| p(event));
+        if fired && self.capture.is_none() {
+            self.capture = Some(Capture {
+                samples: self.history.iter().cloned().collect(),
+                trigger: self.history.len() / self.channels as usize,
+                remaining: self.post,
+            });
+        }
+
+        let Event::Raw(raw) = event else {
+            return self.complete();
+        };
+        if let Some(capture) = &mut self.capture {
+            let take = capture.remaining.min(raw.data.len());
+            capture.samples.extend_from_slice(&raw.data[..take]);
+            capture.remaining -= take;
+        }
+        self.history.extend(raw.data.iter());
+        let excess = self.history.len().saturating_sub(self.pre);
+        self.history.drain(..excess);
+
+        self.complete()
+    }
+
+    fn complete(&mut self) -> Option<Snapshot> {
+        if self.capture.as_ref()?.remaining > 0 {
+            return None;
+        }
+        let capture = self.capture.take()?;
+        Some(Snapshot {
+            samples: capture.samples,
+            channels: self.channels,
+            rate: self.rate,
+            trigger: capture.trigger,
+        })
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::error::{AirapError, AirapErrorKind};
+
+use super::AudioWriter;
+
+/// Size of the header before the samples
+const HEADER: u64 = 44;
+/// The riff sizes are 32 bit, so a file can't hold more
+const MAX_BYTES: u64 = u32::MAX as u64;
+
+/// 32 bit float wav
+pub struct WavWriter {
+    writer: BufWriter<File>,
+    /// Bytes of sample data
+    data: u64,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, channels: u16, rate: u32) -> Result<Self, AirapError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let block_align = channels * 4;
+        writer.write_all(b"RIFF")?;
+        // sizes are written when finishing
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // ieee float
+        writer.write_all(&3u16.to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&rate.to_le_bytes())?;
+        writer.write_all(&(rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&32u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(Self { writer, data: 0 })
+    }
+}
+
+impl AudioWriter for WavWriter {
+    fn write(&mut self, samples: &[f32]) -> Result<(), AirapError> {
+        let bytes = samples.len() as u64 * 4;
+        if !self.fits(samples.len()) {
+            return Err(AirapError::new(
+                format!(
+                    "Wav file would exceed {MAX_BYTES} bytes with {bytes} more bytes of samples"
+                ),
+                AirapErrorKind::Unsupported,
+            ));
+        }
+        for s in samples {
+            self.writer.write_all(&s.to_le_bytes())?;
+        }
+        self.data += bytes;
+        Ok(())
+    }
+
+    fn bytes(&self) -> u64 {
+        HEADER + self.data
+    }
+
+    fn fits(&self, samples: usize) -> bool {
+        HEADER + self.data + samples as u64 * 4 <= MAX_BYTES
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), AirapError> {
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        file.seek(SeekFrom::Start(4))?;
+        file.write_all(&((HEADER - 8 + self.data) as u32).to_le_bytes())?;
+        file.seek(SeekFrom::Start(HEADER - 4))?;
+        file.write_all(&(self.data as u32).to_le_bytes())?;
+        Ok(())
+    }
+}
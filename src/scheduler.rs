@@ -0,0 +1,225 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crossbeam::channel::Receiver;
+
+use crate::feature::{feature_flags, Processor};
+use crate::Event;
+
+#[derive(Debug, Clone)]
+pub struct SchedulerOptions {
+    /// Threads that process the features
+    pub workers: usize,
+    /// Cheap features processed on the thread that dispatches the events instead of a worker
+    pub inline: u32,
+}
+
+impl Default for SchedulerOptions {
+    fn default() -> Self {
+        Self {
+            workers: thread::available_parallelism().map_or(4, |n| n.get()),
+            inline: feature_flags::MOVING_AVERAGE | feature_flags::LEVEL,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    events: AtomicU64,
+    /// Nanoseconds
+    busy: AtomicU64,
+}
+
+/// Time spent processing and events processed per feature, can be read while listening.
+/// Times are the cpu time of the thread that processed the event, so the time a worker was
+/// preempted or waited on a lock is left out.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureStats(Arc<[Counters; 32]>);
+
+impl FeatureStats {
+    /// Time the feature spent processing events
+    pub fn busy(&self, flag: u32) -> Duration {
+        Duration::from_nanos(self.counters(flag).busy.load(Ordering::Relaxed))
+    }
+
+    /// Events the feature processed
+    pub fn events(&self, flag: u32) -> u64 {
+        self.counters(flag).events.load(Ordering::Relaxed)
+    }
+
+    /// Average time the feature needs for an event
+    pub fn average(&self, flag: u32) -> Duration {
+        let events = self.events(flag);
+        if events == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos((self.busy(flag).as_nanos() / events as u128) as u64)
+    }
+
+    pub fn reset(&self) {
+        for counters in self.0.iter() {
+            counters.events.store(0, Ordering::Relaxed);
+            counters.busy.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn counters(&self, flag: u32) -> &Counters {
+        &self.0[flag.trailing_zeros() as usize % 32]
+    }
+
+    fn add(&self, flag: u32, busy: Duration) {
+        let counters = self.counters(flag);
+        counters.events.fetch_add(1, Ordering::Relaxed);
+        counters
+            .busy
+            .fetch_add(busy.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// A feature of the graph, its events are processed in order by one thread at a time
+pub(crate) struct Node {
+    pub flag: u32,
+    /// Events of these features get sent to this node
+    pub dependencies: u32,
+    pub inline: bool,
+    processor: Mutex<Box<dyn Processor>>,
+    /// Events waiting for a worker and whether the node is queued for one
+    queue: Mutex<(VecDeque<Event<'static>>, bool)>,
+    /// Events sent to this node that are not processed yet
+    pub pending: AtomicUsize,
+}
+
+impl Node {
+    pub fn new(flag: u32, dependencies: u32, inline: bool, processor: Box<dyn Processor>) -> Self {
+        Self {
+            flag,
+            dependencies,
+            inline,
+            processor: Mutex::new(processor),
+            queue: Mutex::new((VecDeque::new(), false)),
+            pending: AtomicUsize::new(0),
+        }
+    }
+
+    /// Queue an event, returns true when the node has to be handed to a worker
+    pub fn push(&self, event: Event<'static>) -> bool {
+        self.pending.fetch_add(1, Ordering::Release);
+        let mut queue = self.queue.lock().unwrap();
+        queue.0.push_back(event);
+        !std::mem::replace(&mut queue.1, true)
+    }
+
+    pub fn process(
+        &self,
+        event: &Event,
+        emit: &mut dyn FnMut(Event<'static>),
+        stats: &FeatureStats,
+    ) {
+        let mut processor = self.processor.lock().unwrap();
+        let start = thread_cpu_time();
+        processor.process(event, emit);
+        stats.add(self.flag, thread_cpu_time().saturating_sub(start));
+    }
+
+    /// Process the queued events until none are left
+    fn drain(&self, event_tx: &Sender<Event<'static>>, stats: &FeatureStats) {
+        loop {
+            let event = {
+                let mut queue = self.queue.lock().unwrap();
+                match queue.0.pop_front() {
+                    Some(event) => event,
+                    None => {
+                        queue.1 = false;
+                        return;
+                    }
+                }
+            };
+            // the pool stopped when nobody receives anymore
+            self.process(
+                &event,
+                &mut |e| {
+                    let _ = event_tx.send(e);
+                },
+                stats,
+            );
+            // emitted before counting the event as processed
+            self.pending.fetch_sub(1, Ordering::Release);
+        }
+    }
+}
+
+/// Cpu time used by the calling thread
+fn thread_cpu_time() -> Duration {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // only fails for an invalid clock or pointer
+    unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+/// Spawn a worker that processes the nodes it receives until the pool is dropped
+pub(crate) fn spawn_worker(
+    index: usize,
+    work_rx: Receiver<Arc<Node>>,
+    event_tx: Sender<Event<'static>>,
+    stats: FeatureStats,
+) -> thread::JoinHandle<()> {
+    thread::Builder::new()
+        .name(format!("worker-{index}"))
+        .spawn(move || {
+            while let Ok(node) = work_rx.recv() {
+                node.drain(&event_tx, &stats);
+            }
+        })
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    /// Sleeps or spins for the duration on every event
+    struct Busy {
+        duration: Duration,
+        spin: bool,
+    }
+
+    impl Processor for Busy {
+        fn process(&mut self, _: &Event, _: &mut dyn FnMut(Event<'static>)) {
+            if !self.spin {
+                return thread::sleep(self.duration);
+            }
+            let start = Instant::now();
+            while start.elapsed() < self.duration {
+                std::hint::spin_loop();
+            }
+        }
+    }
+
+    #[test]
+    fn stats_count_cpu_time() {
+        let stats = FeatureStats::default();
+        let duration = Duration::from_millis(20);
+        for (flag, spin) in [(feature_flags::PITCH, true), (feature_flags::TEMPO, false)] {
+            let node = Node::new(flag, 0, true, Box::new(Busy { duration, spin }));
+            for _ in 0..2 {
+                node.process(&Event::DefaultDeviceChange, &mut |_| {}, &stats);
+            }
+        }
+
+        assert_eq!(stats.events(feature_flags::PITCH), 2);
+        // the spinning thread can be preempted as well
+        assert!(stats.average(feature_flags::PITCH) > duration / 2);
+        assert!(stats.busy(feature_flags::TEMPO) < duration / 2);
+        stats.reset();
+        assert_eq!(stats.events(feature_flags::PITCH), 0);
+    }
+}
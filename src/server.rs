@@ -0,0 +1,521 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crossbeam::channel::{bounded, RecvTimeoutError, Sender, TrySendError};
+use log::{info, warn};
+use tungstenite::handshake::server::{Request, Response};
+use tungstenite::{Message, WebSocket};
+
+use crate::error::AirapError;
+use crate::feature::Feature;
+use crate::fields::{capture_time, fields, Value};
+use crate::Event;
+
+/// How long a client thread waits for a frame before checking for messages of the client
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `{"feature": "pitch", "time": 1700000000.123, "frequency": 440, ...}`
+    Json,
+    /// Little endian: feature flag u32, capture time in unix seconds f64, then every field in
+    /// the order of the json object as a type byte followed by the value
+    /// (0 bool u8, 1 int i64, 2 float f32, 3 text u32 length + utf8,
+    /// 4 floats u32 length + f32s, 5 ints u32 length + i64s, 6 notes u32 length + note and velocity u8s,
+    /// 7 time in unix seconds f64)
+    Binary,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    /// Format of clients that don't choose one
+    pub format: Format,
+    /// Events per second per continuous feature of clients that don't choose a rate, unlimited
+    /// when `None`. Events that mark a change like beats or silence are never limited.
+    pub rate: Option<f32>,
+    /// Frames buffered for a slow client before new frames get dropped
+    pub queue: usize,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            format: Format::Json,
+            rate: None,
+            queue: 64,
+        }
+    }
+}
+
+/// What a client receives, set with the query of the url (`ws://host:port/?features=pitch,beat&format=binary&rate=30`)
+/// and changed afterwards by sending a text message with the same syntax
+#[derive(Debug, Clone)]
+struct Selection {
+    /// Feature flags, every feature when 0
+    features: u32,
+    format: Format,
+    /// Minimum time between two events of the same feature
+    interval: Option<Duration>,
+    last: HashMap<u32, std::time::Instant>,
+}
+
+impl Selection {
+    fn new(options: &ServerOptions) -> Self {
+        Self {
+            features: 0,
+            format: options.format,
+            interval: options.rate.map(|r| Duration::from_secs_f32(1.0 / r)),
+            last: HashMap::new(),
+        }
+    }
+
+    fn update(&mut self, query: &str) {
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            match key {
+                "features" => {
+                    self.features = 0;
+                    for name in value.split(',').filter(|n| !n.is_empty()) {
+                        match Feature::from_str(name) {
+                            Ok(feature) => self.features |= feature.to_flag(),
+                            Err(e) => warn!("{e}"),
+                        }
+                    }
+                }
+                "format" => match value {
+                    "json" => self.format = Format::Json,
+                    "binary" => self.format = Format::Binary,
+                    _ => warn!("Unknown format '{value}'"),
+                },
+                "rate" => match value.parse::<f32>() {
+                    Ok(rate) if rate > 0.0 => {
+                        self.interval = Some(Duration::from_secs_f32(1.0 / rate))
+                    }
+                    _ => self.interval = None,
+                },
+                _ => warn!("Unknown option '{key}'"),
+            }
+        }
+    }
+
+    /// Whether the client wants the event now, also registers it for rate limiting. The rate only
+    /// limits continuous features, events that mark a change are always sent.
+    fn accepts(&mut self, flag: u32, change: bool) -> bool {
+        if self.features != 0 && self.features & flag == 0 {
+            return false;
+        }
+        let Some(interval) = self.interval.filter(|_| !change) else {
+            return true;
+        };
+        let now = std::time::Instant::now();
+        match self.last.get(&flag) {
+            Some(last) if now.duration_since(*last) < interval => false,
+            _ => {
+                self.last.insert(flag, now);
+                true
+            }
+        }
+    }
+}
+
+/// Whether the event marks a change of state that is lost when dropped, instead of a measurement
+/// that the next event of the feature replaces
+fn is_change(event: &Event) -> bool {
+    match event {
+        Event::DefaultDeviceChange | Event::Beat(_) | Event::Key(_) | Event::Silence(_) => true,
+        Event::VoiceActivity(e) => e.changed,
+        Event::Notes(e) => !e.onsets.is_empty() || !e.offsets.is_empty(),
+        _ => false,
+    }
+}
+
+struct Client {
+    tx: Sender<Message>,
+    selection: Arc<Mutex<Selection>>,
+}
+
+/// Streams events to websocket clients, call `send` from the callback of `Runner::listen`
+///
+/// ```no_run
+/// let server = airap::server::Server::bind("0.0.0.0:9001", Default::default())?;
+/// airap::Runner::new().listen(move |e| server.send(&e))?;
+/// # Ok::<(), airap::error::AirapError>(())
+/// ```
+pub struct Server {
+    clients: Arc<Mutex<Vec<Client>>>,
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    accept: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(address: A, options: ServerOptions) -> Result<Self, AirapError> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let clients: Arc<Mutex<Vec<Client>>> = Arc::default();
+
+        let accepted = clients.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let accept = thread::Builder::new()
+            .name("server".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let stream = match stream {
+                        Ok(s) => s,
+                        Err(e) => {
+                            warn!("Failed to accept client: {e}");
+                            continue;
+                        }
+                    };
+                    let (options, clients) = (options.clone(), accepted.clone());
+                    let spawned =
+                        thread::Builder::new()
+                            .name("server client".into())
+                            .spawn(move || {
+                                if let Err(e) = serve(stream, &options, &clients) {
+                                    warn!("{e}");
+                                }
+                            });
+                    if let Err(e) = spawned {
+                        warn!("Failed to spawn client thread: {e}");
+                    }
+                }
+            })?;
+
+        info!("Streaming events on {address}");
+        Ok(Self {
+            clients,
+            address,
+            stop,
+            accept: Some(accept),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn send(&self, event: &Event) {
+        let flag = event.to_flag();
+        let change = is_change(event);
+        let mut json = None;
+        let mut binary = None;
+        self.clients.lock().unwrap().retain(|client| {
+            let format = {
+                let mut selection = client.selection.lock().unwrap();
+                if !selection.accepts(flag, change) {
+                    return true;
+                }
+                selection.format
+            };
+            let message = match format {
+                Format::Json => json.get_or_insert_with(|| Message::text(encode_json(event))),
+                Format::Binary => {
+                    binary.get_or_insert_with(|| Message::binary(encode_binary(event)))
+                }
+            };
+            match client.tx.try_send(message.clone()) {
+                Ok(()) | Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+    }
+}
+
+impl Drop for Server {
+    /// Stops accepting clients and disconnects the connected ones
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // wake the accept thread with a connection of our own
+        let mut address = self.address;
+        if address.ip().is_unspecified() {
+            address.set_ip(match address {
+                SocketAddr::V4(_) => [127, 0, 0, 1].into(),
+                SocketAddr::V6(_) => [0, 0, 0, 0, 0, 0, 0, 1].into(),
+            });
+        }
+        if let Err(e) = TcpStream::connect(address) {
+            warn!("Failed to wake the server thread: {e}");
+        } else if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+        // client threads stop when their channel disconnects
+        self.clients.lock().unwrap().clear();
+    }
+}
+
+/// Handshake and stream to a single client until it disconnects
+// the error type of the handshake callback is defined by tungstenite
+#[allow(clippy::result_large_err)]
+fn serve(
+    stream: TcpStream,
+    options: &ServerOptions,
+    clients: &Mutex<Vec<Client>>,
+) -> Result<(), AirapError> {
+    let peer = stream.peer_addr()?;
+    let selection = Arc::new(Mutex::new(Selection::new(options)));
+
+    let mut socket = tungstenite::accept_hdr(stream, |request: &Request, response: Response| {
+        if let Some(query) = request.uri().query() {
+            selection.lock().unwrap().update(query);
+        }
+        Ok(response)
+    })
+    .map_err(|e| AirapError::unsupported(format!("Websocket handshake with {peer} failed: {e}")))?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(1)))?;
+
+    let (tx, rx) = bounded::<Message>(options.queue);
+    clients.lock().unwrap().push(Client {
+        tx,
+        selection: selection.clone(),
+    });
+
+    info!("Client {peer} connected");
+    loop {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(message) => {
+                if socket.send(message).is_err() {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if !read(&mut socket, &selection) {
+            break;
+        }
+    }
+    info!("Client {peer} disconnected");
+    Ok(())
+}
+
+/// Handles everything the client sent, false when the connection is gone
+fn read(socket: &mut WebSocket<TcpStream>, selection: &Mutex<Selection>) -> bool {
+    loop {
+        match socket.read() {
+            Ok(Message::Text(query)) => selection.lock().unwrap().update(query.as_str()),
+            Ok(Message::Close(_)) => return false,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                return true
+            }
+            Err(_) => return false,
+        }
+    }
+}
+
+fn seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs_f64()
+}
+
+/// Quoted json string of `s`
+fn string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+fn encode_json(event: &Event) -> String {
+    let float = |json: &mut String, f: f32| {
+        if f.is_finite() {
+            write!(json, "{f}").unwrap();
+        } else {
+            json.push_str("null");
+        }
+    };
+    let floats = |json: &mut String, values: &[f32]| {
+        json.push('[');
+        for (i, v) in values.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            float(json, *v);
+        }
+        json.push(']');
+    };
+
+    let mut json = "{\"feature\":".to_string();
+    string(&mut json, &Feature::default(event.to_flag()).to_string());
+    write!(json, ",\"time\":{}", seconds(capture_time(event))).unwrap();
+    for (field, value) in fields(event) {
+        json.push(',');
+        string(&mut json, field);
+        json.push(':');
+        match value {
+            Value::Bool(b) => write!(json, "{b}").unwrap(),
+            Value::Int(i) => write!(json, "{i}").unwrap(),
+            Value::Float(f) => float(&mut json, f),
+            Value::Text(s) => string(&mut json, s),
+            Value::Time(t) => write!(json, "{}", seconds(t)).unwrap(),
+            Value::Floats(values) | Value::Samples(values) => floats(&mut json, values),
+            Value::Ints(values) => write!(json, "{values:?}").unwrap(),
+            Value::Notes(notes) => {
+                let pairs: Vec<[u8; 2]> = notes.iter().map(|n| [n.note, n.velocity]).collect();
+                write!(json, "{pairs:?}").unwrap()
+            }
+        }
+    }
+    json.push('}');
+    json
+}
+
+fn encode_binary(event: &Event) -> Vec<u8> {
+    let mut frame = vec![];
+    frame.extend_from_slice(&event.to_flag().to_le_bytes());
+    frame.extend_from_slice(&seconds(capture_time(event)).to_le_bytes());
+    for (_, value) in fields(event) {
+        match value {
+            Value::Bool(b) => frame.extend_from_slice(&[0, b as u8]),
+            Value::Int(i) => {
+                frame.push(1);
+                frame.extend_from_slice(&i.to_le_bytes());
+            }
+            Value::Float(f) => {
+                frame.push(2);
+                frame.extend_from_slice(&f.to_le_bytes());
+            }
+            Value::Text(s) => {
+                frame.push(3);
+                frame.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                frame.extend_from_slice(s.as_bytes());
+            }
+            Value::Time(t) => {
+                frame.push(7);
+                frame.extend_from_slice(&seconds(t).to_le_bytes());
+            }
+            Value::Floats(values) | Value::Samples(values) => {
+                frame.push(4);
+                frame.extend_from_slice(&(values.len() as u32).to_le_bytes());
+                values
+                    .iter()
+                    .for_each(|v| frame.extend_from_slice(&v.to_le_bytes()));
+            }
+            Value::Ints(values) => {
+                frame.push(5);
+                frame.extend_from_slice(&(values.len() as u32).to_le_bytes());
+                values
+                    .iter()
+                    .for_each(|v| frame.extend_from_slice(&v.to_le_bytes()));
+            }
+            Value::Notes(notes) => {
+                frame.push(6);
+                frame.extend_from_slice(&(notes.len() as u32).to_le_bytes());
+                notes
+                    .iter()
+                    .for_each(|n| frame.extend_from_slice(&[n.note, n.velocity]));
+            }
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::feature_flags;
+    use crate::feature::tempo::TempoEvent;
+    use crate::latency::{Instant, Latency};
+
+    fn tempo() -> Event<'static> {
+        Event::Tempo(TempoEvent {
+            bpm: 120.0,
+            confidence: 0.5,
+            latency: Latency {
+                internal: Instant::None,
+                airap: Instant::None,
+                captured: UNIX_EPOCH + Duration::from_millis(1_700_000_000_250),
+            },
+        })
+    }
+
+    #[test]
+    fn selection_from_query() {
+        let mut selection = Selection::new(&ServerOptions::default());
+        selection.update("features=pitch,beat,unknown&format=binary&rate=30");
+        assert_eq!(
+            selection.features,
+            feature_flags::PITCH | feature_flags::BEAT
+        );
+        assert_eq!(selection.format, Format::Binary);
+        assert_eq!(
+            selection.interval,
+            Some(Duration::from_secs_f32(1.0 / 30.0))
+        );
+
+        selection.update("features=&rate=0");
+        assert_eq!(selection.features, 0);
+        assert_eq!(selection.format, Format::Binary);
+        assert_eq!(selection.interval, None);
+    }
+
+    #[test]
+    fn rate_limits_continuous_features() {
+        let options = ServerOptions {
+            rate: Some(1.0),
+            ..Default::default()
+        };
+        let mut selection = Selection::new(&options);
+        selection.update("features=tempo,beat");
+        assert!(selection.accepts(feature_flags::TEMPO, false));
+        assert!(!selection.accepts(feature_flags::TEMPO, false));
+        // every feature has its own limit and changes are never limited
+        assert!(selection.accepts(feature_flags::BEAT, false));
+        assert!(selection.accepts(feature_flags::BEAT, true));
+        assert!(!selection.accepts(feature_flags::PITCH, true));
+    }
+
+    #[test]
+    fn json_escapes_text() {
+        let mut json = String::new();
+        string(&mut json, "say \"hi\" \\ \n");
+        assert_eq!(json, r#""say \"hi\" \\ \u000a""#);
+        assert_eq!(
+            encode_json(&tempo()),
+            r#"{"feature":"tempo","time":1700000000.25,"bpm":120,"confidence":0.5}"#
+        );
+    }
+
+    #[test]
+    fn binary_layout() {
+        let mut expected = feature_flags::TEMPO.to_le_bytes().to_vec();
+        expected.extend_from_slice(&1_700_000_000.25f64.to_le_bytes());
+        expected.push(2);
+        expected.extend_from_slice(&120f32.to_le_bytes());
+        expected.push(2);
+        expected.extend_from_slice(&0.5f32.to_le_bytes());
+        assert_eq!(encode_binary(&tempo()), expected);
+    }
+
+    #[test]
+    fn drop_stops_accepting() {
+        let server = Server::bind("127.0.0.1:0", Default::default()).unwrap();
+        let address = server.local_addr();
+        drop(server);
+        assert!(TcpStream::connect(address).is_err());
+    }
+}
@@ -0,0 +1,295 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AirapError, AirapErrorKind};
+use crate::feature::Feature;
+use crate::{Device, Event};
+
+/// First line of a session file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Header {
+    device: Device,
+    features: Vec<Feature>,
+}
+
+/// An event and when it was emitted, relative to the start of the session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub time: Duration,
+    pub event: Event<'static>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaySpeed {
+    /// Raw audio is fed at the pace it was recorded
+    Original,
+    /// Raw audio is fed as fast as the features can process it
+    Fast,
+}
+
+/// Writes every event of a session as json lines, used by `Runner::record`
+pub(crate) struct SessionRecorder {
+    writer: Mutex<BufWriter<File>>,
+    start: Instant,
+}
+
+impl SessionRecorder {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        device: &Device,
+        features: &[Feature],
+    ) -> Result<Self, AirapError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let header = Header {
+            device: device.clone(),
+            features: features.to_vec(),
+        };
+        serde_json::to_writer(&mut writer, &header).map_err(json_error)?;
+        writer.write_all(b"\n")?;
+        Ok(Self {
+            writer: Mutex::new(writer),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&self, event: &Event) {
+        let record = Record {
+            time: self.start.elapsed(),
+            event: event.clone().into_owned(),
+        };
+        let mut writer = self.writer.lock().unwrap();
+        let written = serde_json::to_writer(&mut *writer, &record)
+            .map_err(json_error)
+            .and_then(|_| Ok(writer.write_all(b"\n")?));
+        if let Err(e) = written {
+            warn!("Failed to record event: {e}");
+        }
+    }
+}
+
+impl Drop for SessionRecorder {
+    fn drop(&mut self) {
+        if let Ok(writer) = self.writer.get_mut() {
+            let _ = writer.flush();
+        }
+    }
+}
+
+/// A recorded session, replay it with `Runner::replay`
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub device: Device,
+    /// Features subscribed while recording, including dependencies
+    pub features: Vec<Feature>,
+    pub records: Vec<Record>,
+}
+
+impl Session {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, AirapError> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header: Header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?).map_err(json_error)?,
+            None => return Err(AirapError::new("Empty session file", AirapErrorKind::Io)),
+        };
+        let records = lines
+            .map(|line| serde_json::from_str(&line?).map_err(json_error))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            device: header.device,
+            features: header.features,
+            records,
+        })
+    }
+
+    /// Recorded events of the feature flags, e.g. to compare with the events of a replay
+    pub fn events(&self, flags: u32) -> impl Iterator<Item = &Event<'static>> {
+        self.records
+            .iter()
+            .map(|r| &r.event)
+            .filter(move |e| e.to_flag() & flags > 0)
+    }
+}
+
+/// Session that takes the place of the audio device while listening
+#[derive(Debug, Clone)]
+pub(crate) struct Replay {
+    pub session: Arc<Session>,
+    pub speed: ReplaySpeed,
+}
+
+impl Replay {
+    /// Send the recorded raw audio to `cb` and set `finished` after the last block, or return early
+    /// when `stop` is set
+    pub fn run<F>(&self, finished: &AtomicBool, stop: &AtomicBool, mut cb: F)
+    where
+        F: FnMut(Event<'static>),
+    {
+        let start = Instant::now();
+        for record in self.session.records.iter() {
+            if stop.load(Ordering::Acquire) {
+                return;
+            }
+            if !matches!(record.event, Event::Raw(_)) {
+                continue;
+            }
+            if self.speed == ReplaySpeed::Original {
+                if let Some(wait) = record.time.checked_sub(start.elapsed()) {
+                    thread::sleep(wait);
+                }
+            }
+            cb(record.event.clone());
+        }
+        finished.store(true, Ordering::Release);
+    }
+}
+
+fn json_error(error: serde_json::Error) -> AirapError {
+    AirapError::new(format!("Invalid session: {error}"), AirapErrorKind::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::feature::band::{Band, BandEnergy, BandFilter};
+    use crate::feature::feature_flags;
+    use crate::feature::level::Level;
+    use crate::feature::loudness::{Loudness, LoudnessReset};
+    use crate::feature::silence::Silence;
+    use crate::feature::testing::{clicks, run, sine, spec};
+    use crate::latency::{Instant, Latency};
+    use crate::RawEvent;
+
+    fn device() -> Device {
+        Device {
+            name: "test".into(),
+            spec: spec(),
+            monitor_of_sink_name: None,
+        }
+    }
+
+    fn raw(data: Vec<f32>, captured: SystemTime) -> Event<'static> {
+        Event::Raw(RawEvent {
+            data: Cow::Owned(data),
+            latency: Latency {
+                internal: Instant::None,
+                airap: Instant::None,
+                captured,
+            },
+        })
+    }
+
+    fn record(events: &[Event], features: &[Feature]) -> Session {
+        let path = std::env::temp_dir().join(format!(
+            "airap-session-{}-{}.jsonl",
+            std::process::id(),
+            events.len()
+        ));
+        let recorder = SessionRecorder::create(&path, &device(), features).unwrap();
+        for event in events.iter() {
+            recorder.record(event);
+        }
+        drop(recorder);
+        let session = Session::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        session
+    }
+
+    #[test]
+    fn round_trip_with_silence() {
+        let spec = spec();
+        // silent input makes the loudness, level, band energy and silence level -inf
+        let mut events = run(
+            vec![
+                (
+                    feature_flags::RAW,
+                    Box::new(Loudness::new(10.0, &spec, LoudnessReset::default())),
+                ),
+                (
+                    feature_flags::RAW,
+                    Box::new(Level::new(10.0, 300.0, 1500.0, 30.0, &spec)),
+                ),
+                (
+                    feature_flags::RAW,
+                    Box::new(Silence::new(-60.0, 6.0, 100.0, &spec)),
+                ),
+                (
+                    feature_flags::RAW,
+                    Box::new(BandEnergy::new(
+                        &Band::bass_mid_treble(),
+                        BandFilter::Iir { rate: 30.0 },
+                        0.0,
+                        &spec,
+                    )),
+                ),
+            ],
+            &[0.0; 48000],
+        );
+        assert!(events.iter().any(|e| match e {
+            Event::Loudness(l) => l.momentary == f32::NEG_INFINITY,
+            _ => false,
+        }));
+        events.push(raw(
+            vec![0.0, f32::NAN, f32::INFINITY, f32::NEG_INFINITY],
+            SystemTime::UNIX_EPOCH,
+        ));
+
+        let session = record(&events, &[Feature::default(feature_flags::RAW)]);
+        assert_eq!(session.records.len(), events.len());
+        for (record, event) in session.records.iter().zip(events) {
+            assert_eq!(format!("{:?}", record.event), format!("{event:?}"));
+        }
+    }
+
+    #[test]
+    fn replay_is_deterministic() {
+        // clicks at 120 bpm on a quiet sine
+        let samples: Vec<f32> = clicks(120.0, 4.0)
+            .iter()
+            .zip(sine(440.0, 0.1, 4.0))
+            .map(|(a, b)| a + b)
+            .collect();
+        let blocks: Vec<_> = samples
+            .chunks(480)
+            .enumerate()
+            .map(|(i, block)| {
+                let captured = SystemTime::UNIX_EPOCH + Duration::from_millis(10 * i as u64);
+                raw(block.to_vec(), captured)
+            })
+            .collect();
+        let session = record(&blocks, &[Feature::default(feature_flags::RAW)]);
+
+        let replay = || {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let collected = events.clone();
+            crate::Runner::new()
+                .replay(session.clone(), ReplaySpeed::Fast)
+                .subscribe(&[
+                    Feature::default(feature_flags::BEAT),
+                    Feature::default(feature_flags::SPECTRAL_DESCRIPTORS),
+                    Feature::default(feature_flags::LEVEL),
+                ])
+                .unwrap()
+                .listen(move |e| {
+                    if e.to_flag() != feature_flags::RAW {
+                        collected.lock().unwrap().push(format!("{e:?}"));
+                    }
+                })
+                .unwrap();
+            Arc::try_unwrap(events).unwrap().into_inner().unwrap()
+        };
+        let first = replay();
+        assert!(!first.is_empty());
+        assert_eq!(first, replay());
+    }
+}
@@ -0,0 +1,67 @@
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_channel::mpsc::UnboundedReceiver;
+use futures_core::Stream;
+
+use crate::Event;
+
+/// Events of a `Runner`, returned by `Runner::events`. Dropping it stops the audio and the
+/// feature threads.
+pub struct EventStream {
+    rx: UnboundedReceiver<Event<'static>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl EventStream {
+    pub(crate) fn new(rx: UnboundedReceiver<Event<'static>>, stop: Arc<AtomicBool>) -> Self {
+        Self { rx, stop }
+    }
+
+    /// Only the events of the feature flags
+    pub fn filter(self, flags: u32) -> FilteredEventStream {
+        FilteredEventStream {
+            stream: self,
+            flags,
+        }
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Event<'static>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rx.size_hint()
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+    }
+}
+
+/// Events of some features, returned by `EventStream::filter`
+pub struct FilteredEventStream {
+    stream: EventStream,
+    flags: u32,
+}
+
+impl Stream for FilteredEventStream {
+    type Item = Event<'static>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(event)) if event.to_flag() & self.flags == 0 => continue,
+                poll => return poll,
+            }
+        }
+    }
+}